@@ -9,67 +9,554 @@ wit_bindgen::generate!({
 
 pub use hyperware_app_common::SendResult;
 pub use hyperware_app_common::send;
-use hyperware_process_lib::Address;
+use hyperware_app_common::ResponseFuture;
+use hyperware_process_lib::{
+    get_blob,
+    http::{
+        client::{HttpClientAction, HttpClientError, HttpClientResponse, OutgoingHttpRequest},
+        server::StatusCode,
+        Method,
+    },
+    timer::TimerAction,
+    Address, Request, SendError, SendErrorKind,
+};
+use serde::de::DeserializeOwned;
 use serde_json::json;
+use std::collections::HashMap;
+use std::future::Future;
+
+/// Timeout, retry and backoff settings accepted by every RPC stub.
+/// The default matches the old behaviour: a 30 second timeout and no retries.
+#[derive(Debug, Clone)]
+pub struct CallOptions {
+    /// Seconds to wait for each attempt
+    pub timeout: u64,
+    /// Extra attempts after the first one fails with a retryable error
+    pub retries: u32,
+    /// Wait before the first retry; doubled for each retry after that
+    pub initial_backoff_ms: u64,
+    /// Upper bound on the wait between retries
+    pub max_backoff_ms: u64,
+    /// Retry when the target did not answer in time; idempotent calls only
+    pub retry_on_timeout: bool,
+    /// Retry when the target could not be reached
+    pub retry_on_offline: bool,
+    /// Retry when the response could not be decoded; idempotent calls only
+    pub retry_on_deserialization_error: bool,
+    /// Retry an HTTP call answered with 502, 503 or 504; idempotent calls only
+    pub retry_on_unavailable: bool,
+    /// Extra headers sent with every HTTP call, e.g. the node's auth cookie
+    pub headers: HashMap<String, String>,
+}
+
+impl Default for CallOptions {
+    fn default() -> Self {
+        Self {
+            timeout: 30,
+            retries: 0,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 10_000,
+            retry_on_timeout: true,
+            retry_on_offline: true,
+            retry_on_deserialization_error: false,
+            retry_on_unavailable: true,
+            headers: HashMap::new(),
+        }
+    }
+}
+
+impl CallOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn timeout(mut self, timeout: u64) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn backoff(mut self, initial_ms: u64, max_ms: u64) -> Self {
+        self.initial_backoff_ms = initial_ms;
+        self.max_backoff_ms = max_ms;
+        self
+    }
+
+    pub fn retry_on_timeout(mut self, retry: bool) -> Self {
+        self.retry_on_timeout = retry;
+        self
+    }
+
+    pub fn retry_on_offline(mut self, retry: bool) -> Self {
+        self.retry_on_offline = retry;
+        self
+    }
+
+    pub fn retry_on_deserialization_error(mut self, retry: bool) -> Self {
+        self.retry_on_deserialization_error = retry;
+        self
+    }
+
+    pub fn retry_on_unavailable(mut self, retry: bool) -> Self {
+        self.retry_on_unavailable = retry;
+        self
+    }
+
+    /// Add a header to every HTTP call, e.g. `("Cookie", "hyperware-auth_...=...")`
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Whether a failed attempt should be tried again. A call that is not `idempotent`
+    /// may already have run after a timeout or bad response, so only `Offline` is retried.
+    pub fn is_retryable<T: Retryable>(&self, result: &T, idempotent: bool) -> bool {
+        result.is_retryable(self, idempotent)
+    }
+
+    /// Wait before retry number `attempt` (0-based)
+    pub fn backoff_for(&self, attempt: u32) -> u64 {
+        self.initial_backoff_ms
+            .saturating_mul(1u64 << attempt.min(32))
+            .min(self.max_backoff_ms)
+    }
+}
+
+/// Results `with_retries` can classify
+pub trait Retryable {
+    /// Whether this attempt failed in a way `options` allows retrying
+    fn is_retryable(&self, options: &CallOptions, idempotent: bool) -> bool;
+}
+
+impl<R> Retryable for SendResult<R> {
+    fn is_retryable(&self, options: &CallOptions, idempotent: bool) -> bool {
+        match self {
+            SendResult::Success(_) => false,
+            SendResult::Timeout => idempotent && options.retry_on_timeout,
+            SendResult::Offline => options.retry_on_offline,
+            SendResult::DeserializationError(_) => idempotent && options.retry_on_deserialization_error,
+        }
+    }
+}
+
+/// Outcome of an HTTP RPC: the `SendResult` cases plus a reply that did not come from the
+/// handler, e.g. the HTTP server's own login redirect or a proxy error
+#[derive(Debug)]
+pub enum HttpSendResult<R> {
+    Success(R),
+    Timeout,
+    Offline,
+    DeserializationError(String),
+    /// A non-2xx status whose body is not a handler result
+    HttpError { status: u16, body: String },
+}
+
+impl<R> From<SendResult<R>> for HttpSendResult<R> {
+    fn from(result: SendResult<R>) -> Self {
+        match result {
+            SendResult::Success(value) => HttpSendResult::Success(value),
+            SendResult::Timeout => HttpSendResult::Timeout,
+            SendResult::Offline => HttpSendResult::Offline,
+            SendResult::DeserializationError(e) => HttpSendResult::DeserializationError(e),
+        }
+    }
+}
+
+impl<R> Retryable for HttpSendResult<R> {
+    fn is_retryable(&self, options: &CallOptions, idempotent: bool) -> bool {
+        match self {
+            HttpSendResult::Success(_) => false,
+            HttpSendResult::Timeout => idempotent && options.retry_on_timeout,
+            HttpSendResult::Offline => options.retry_on_offline,
+            HttpSendResult::DeserializationError(_) => idempotent && options.retry_on_deserialization_error,
+            HttpSendResult::HttpError { status, .. } => {
+                idempotent && options.retry_on_unavailable && matches!(status, 502 | 503 | 504)
+            }
+        }
+    }
+}
+
+/// Run `call` until it succeeds, fails with a non-retryable error or runs out of retries.
+/// `idempotent` says whether running the call twice is harmless (see `is_retryable`).
+/// The backoff wait is awaited on the node's timer, so the process keeps running meanwhile.
+pub async fn with_retries<T, F, Fut>(options: &CallOptions, idempotent: bool, mut call: F) -> T
+where
+    T: Retryable,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = T>,
+{
+    let mut attempt = 0;
+    loop {
+        let result = call().await;
+        if attempt >= options.retries || !options.is_retryable(&result, idempotent) {
+            return result;
+        }
+        sleep(options.backoff_for(attempt)).await;
+        attempt += 1;
+    }
+}
+
+// Wait `ms` milliseconds on the node's timer without blocking the process
+async fn sleep(ms: u64) {
+    let timer = serde_json::to_vec(&TimerAction::SetTimer(ms)).unwrap_or_default();
+    let request = Request::to(("our", "timer", "distro", "sys")).body(timer);
+    let _ = await_response(request, ms / 1_000 + 1).await;
+}
+
+// Send `request` with a fresh correlation id in its context, which routes the response
+// back to this future, and await the raw response body. `None` if it could not be sent;
+// a request that goes unanswered resolves to the kernel's `SendError`, as JSON.
+async fn await_response(request: Request, timeout: u64) -> Option<Vec<u8>> {
+    let correlation_id = uuid::Uuid::new_v4().to_string();
+    request
+        .expects_response(timeout)
+        .context(correlation_id.as_bytes().to_vec())
+        .send()
+        .ok()?;
+    Some(ResponseFuture::new(correlation_id).await)
+}
+
+/// POST a request body to one of the app's HTTP bindings and decode the typed response.
+/// `target` is the app's base URL, e.g. `http://localhost:8080/message-log-app:message-log-app:template.os`.
+/// No answer within `options.timeout` seconds comes back as `Timeout`, any other transport
+/// failure as `Offline`. Handler errors arrive as a typed `{"Err": ...}` body with their
+/// 4xx/5xx status and decode as `Success(Err(..))`; any other non-2xx reply (e.g. from
+/// the HTTP server's own auth check) comes back as `HttpError`.
+pub async fn send_http<R: DeserializeOwned>(
+    request: &serde_json::Value,
+    target: &str,
+    path: &str,
+    options: &CallOptions,
+) -> HttpSendResult<R> {
+    let (status, body) = match post_json(request, target, path, options).await {
+        Ok(response) => response,
+        Err(failed) => return failed.into(),
+    };
+
+    match serde_json::from_slice::<R>(&body) {
+        Ok(value) => HttpSendResult::Success(value),
+        Err(_) if !status.is_success() => HttpSendResult::HttpError {
+            status: status.as_u16(),
+            body: String::from_utf8_lossy(&body).into_owned(),
+        },
+        Err(e) => HttpSendResult::DeserializationError(format!("failed to decode response: {}", e)),
+    }
+}
+
+// POST `request` as JSON to `target` + `path` through the node's HTTP client, with
+// `options.headers`, returning the status and raw body. The request is awaited like any
+// `send`, so the calling process keeps handling other messages while it is out.
+async fn post_json<R>(
+    request: &serde_json::Value,
+    target: &str,
+    path: &str,
+    options: &CallOptions,
+) -> Result<(StatusCode, Vec<u8>), SendResult<R>> {
+    let url = format!("{}{}", target.trim_end_matches('/'), path);
+    let url = url::Url::parse(&url)
+        .map_err(|e| SendResult::DeserializationError(format!("invalid URL {}: {}", url, e)))?;
+    let body = serde_json::to_vec(request)
+        .map_err(|e| SendResult::DeserializationError(format!("failed to encode request: {}", e)))?;
+    let mut headers = options.headers.clone();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    let action = HttpClientAction::Http(OutgoingHttpRequest {
+        method: Method::POST.to_string(),
+        version: None,
+        url: url.to_string(),
+        headers,
+    });
+    let action = serde_json::to_vec(&action)
+        .map_err(|e| SendResult::DeserializationError(format!("failed to encode request: {}", e)))?;
+
+    let request = Request::to(("our", "http-client", "distro", "sys")).body(action).blob_bytes(body);
+    let response = await_response(request, options.timeout).await.ok_or(SendResult::Offline)?;
+
+    match serde_json::from_slice::<Result<HttpClientResponse, HttpClientError>>(&response) {
+        Ok(Ok(HttpClientResponse::Http(response))) => {
+            let status = StatusCode::from_u16(response.status).map_err(|_| {
+                SendResult::DeserializationError(format!("invalid HTTP status {}", response.status))
+            })?;
+            // The body is the blob of the response we were just woken by
+            let body = get_blob().map(|blob| blob.bytes).unwrap_or_default();
+            Ok((status, body))
+        }
+        Ok(Ok(other)) => Err(SendResult::DeserializationError(format!("unexpected HTTP client response: {:?}", other))),
+        Ok(Err(_)) => Err(SendResult::Offline),
+        // No response at all: the kernel reports why the request went unanswered
+        Err(_) => match serde_json::from_slice::<SendError>(&response) {
+            Ok(error) if matches!(error.kind, SendErrorKind::Timeout) => Err(SendResult::Timeout),
+            Ok(_) => Err(SendResult::Offline),
+            Err(e) => Err(SendResult::DeserializationError(format!("failed to decode response: {}", e))),
+        },
+    }
+}
 
 /// Generated RPC stubs for the app interface
 pub mod app {
     use crate::*;
 
     /// Generated stub for `get-status` http RPC call
-    pub async fn get_status_http_rpc(_target: &str) -> SendResult<StatusResponse> {
-        // TODO: Implement HTTP endpoint
-        SendResult::Success(StatusResponse::default())
+    pub async fn get_status_http_rpc(target: &str, options: &CallOptions) -> HttpSendResult<Result<StatusResponse, AppError>> {
+        let request = json!({"GetStatus" : {}});
+        with_retries(options, true, || send_http::<Result<StatusResponse, AppError>>(&request, target, "/api/status", options)).await
+    }
+    
+    /// Generated stub for `get-stats` http RPC call
+    pub async fn get_stats_http_rpc(target: &str, options: &CallOptions) -> HttpSendResult<Result<StatsResponse, AppError>> {
+        let request = json!({"GetStats" : {}});
+        with_retries(options, true, || send_http::<Result<StatsResponse, AppError>>(&request, target, "/api/stats", options)).await
     }
     
     /// Generated stub for `get-history` http RPC call
-    pub async fn get_history_http_rpc(_target: &str) -> SendResult<HistoryResponse> {
-        // TODO: Implement HTTP endpoint
-        SendResult::Success(HistoryResponse::default())
+    pub async fn get_history_http_rpc(target: &str, query:  HistoryQuery, options: &CallOptions) -> HttpSendResult<Result<HistoryResponse, AppError>> {
+        let request = json!({"GetHistory": query});
+        with_retries(options, true, || send_http::<Result<HistoryResponse, AppError>>(&request, target, "/api/history", options)).await
+    }
+    
+    /// Generated stub for `search-history` http RPC call
+    pub async fn search_history_http_rpc(target: &str, query:  SearchQuery, options: &CallOptions) -> HttpSendResult<Result<SearchResponse, AppError>> {
+        let request = json!({"SearchHistory": query});
+        with_retries(options, true, || send_http::<Result<SearchResponse, AppError>>(&request, target, "/api/search", options)).await
+    }
+    
+    /// Generated stub for `get-entry` http RPC call
+    pub async fn get_entry_http_rpc(target: &str, id:  String, options: &CallOptions) -> HttpSendResult<Result<LogEntry, AppError>> {
+        let request = json!({"GetEntry": id});
+        with_retries(options, true, || send_http::<Result<LogEntry, AppError>>(&request, target, "/api/entry", options)).await
     }
     
     /// Generated stub for `clear-history` http RPC call
-    pub async fn clear_history_http_rpc(_target: &str) -> SendResult<SuccessResponse> {
-        // TODO: Implement HTTP endpoint
-        SendResult::Success(SuccessResponse::default())
+    pub async fn clear_history_http_rpc(target: &str, options: &CallOptions) -> HttpSendResult<Result<ClearResponse, AppError>> {
+        let request = json!({"ClearHistory" : {}});
+        with_retries(options, false, || send_http::<Result<ClearResponse, AppError>>(&request, target, "/api/clear-history", options)).await
+    }
+    
+    /// Generated stub for `undo-clear` http RPC call
+    pub async fn undo_clear_http_rpc(target: &str, options: &CallOptions) -> HttpSendResult<Result<SuccessResponse, AppError>> {
+        let request = json!({"UndoClear" : {}});
+        with_retries(options, false, || send_http::<Result<SuccessResponse, AppError>>(&request, target, "/api/undo-clear", options)).await
+    }
+    
+    /// Generated stub for `export-history` http RPC call
+    pub async fn export_history_http_rpc(target: &str, query:  HistoryQuery, format:  ExportFormat, options: &CallOptions) -> HttpSendResult<Result<ExportResponse, AppError>> {
+        let request = json!({"ExportHistory": (query, format)});
+        with_retries(options, false, || send_http::<Result<ExportResponse, AppError>>(&request, target, "/api/export", options)).await
+    }
+    
+    /// Generated stub for `download-export` http RPC call
+    pub async fn download_export_http_rpc(target: &str, file_name:  String, options: &CallOptions) -> HttpSendResult<Result<DownloadResponse, AppError>> {
+        let request = json!({"DownloadExport": file_name});
+        with_retries(options, true, || send_http::<Result<DownloadResponse, AppError>>(&request, target, "/api/download", options)).await
+    }
+    
+    /// Generated stub for `import-history` http RPC call
+    pub async fn import_history_http_rpc(target: &str, source:  ImportSource, options: &CallOptions) -> HttpSendResult<Result<ImportResponse, AppError>> {
+        let request = json!({"ImportHistory": source});
+        with_retries(options, true, || send_http::<Result<ImportResponse, AppError>>(&request, target, "/api/import", options)).await
     }
     
     /// Generated stub for `log-custom-message` http RPC call
-    pub async fn log_custom_message_http_rpc(_target: &str, _message_type:  String, _content:  String) -> SendResult<SuccessResponse> {
-        // TODO: Implement HTTP endpoint
-        SendResult::Success(SuccessResponse::default())
+    pub async fn log_custom_message_http_rpc(target: &str, message_type:  String, content:  String, severity:  Option<Severity>, attributes:  Vec<(String, String)>, options: &CallOptions) -> HttpSendResult<Result<SuccessResponse, AppError>> {
+        let request = json!({"LogCustomMessage": (message_type, content, severity, attributes)});
+        with_retries(options, false, || send_http::<Result<SuccessResponse, AppError>>(&request, target, "/api/log", options)).await
+    }
+    
+    /// Generated stub for `set-status-timer` http RPC call
+    pub async fn set_status_timer_http_rpc(target: &str, interval_ms:  u64, paused:  bool, options: &CallOptions) -> HttpSendResult<Result<SuccessResponse, AppError>> {
+        let request = json!({"SetStatusTimer": (interval_ms, paused)});
+        with_retries(options, true, || send_http::<Result<SuccessResponse, AppError>>(&request, target, "/api/config", options)).await
+    }
+    
+    /// Generated stub for `log-custom-batch` http RPC call
+    pub async fn log_custom_batch_http_rpc(target: &str, messages:  Vec<ExternalLogMessage>, options: &CallOptions) -> HttpSendResult<Result<BatchResponse, AppError>> {
+        // Resending is only safe when the target can recognise messages it already stored
+        let idempotent = messages.iter().all(|message| message.id.is_some());
+        let request = json!({"LogCustomBatch": messages});
+        with_retries(options, idempotent, || send_http::<Result<BatchResponse, AppError>>(&request, target, "/api/batch", options)).await
+    }
+    
+    /// Generated stub for `get-config` http RPC call
+    pub async fn get_config_http_rpc(target: &str, options: &CallOptions) -> HttpSendResult<Result<AppConfig, AppError>> {
+        let request = json!({"GetConfig" : {}});
+        with_retries(options, true, || send_http::<Result<AppConfig, AppError>>(&request, target, "/api/config", options)).await
+    }
+    
+    /// Generated stub for `set-config` http RPC call
+    pub async fn set_config_http_rpc(target: &str, update:  ConfigUpdate, options: &CallOptions) -> HttpSendResult<Result<AppConfig, AppError>> {
+        let request = json!({"SetConfig": update});
+        with_retries(options, true, || send_http::<Result<AppConfig, AppError>>(&request, target, "/api/config", options)).await
     }
     
     /// Generated stub for `external-get-status` remote RPC call
-    pub async fn external_get_status_remote_rpc(target: &Address) -> SendResult<StatusResponse> {
+    pub async fn external_get_status_remote_rpc(target: &Address, options: &CallOptions) -> SendResult<Result<StatusResponse, AppError>> {
         let request = json!({"ExternalGetStatus" : {}});
-        send::<StatusResponse>(&request, target, 30).await
+        with_retries(options, true, || send::<Result<StatusResponse, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `external-get-stats` remote RPC call
+    pub async fn external_get_stats_remote_rpc(target: &Address, options: &CallOptions) -> SendResult<Result<StatsResponse, AppError>> {
+        let request = json!({"ExternalGetStats" : {}});
+        with_retries(options, true, || send::<Result<StatsResponse, AppError>>(&request, target, options.timeout)).await
     }
     
     /// Generated stub for `external-get-history` remote RPC call
-    pub async fn external_get_history_remote_rpc(target: &Address) -> SendResult<HistoryResponse> {
-        let request = json!({"ExternalGetHistory" : {}});
-        send::<HistoryResponse>(&request, target, 30).await
+    pub async fn external_get_history_remote_rpc(target: &Address, query: HistoryQuery, options: &CallOptions) -> SendResult<Result<HistoryResponse, AppError>> {
+        let request = json!({"ExternalGetHistory": query});
+        with_retries(options, true, || send::<Result<HistoryResponse, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `external-search-history` remote RPC call
+    pub async fn external_search_history_remote_rpc(target: &Address, query: SearchQuery, options: &CallOptions) -> SendResult<Result<SearchResponse, AppError>> {
+        let request = json!({"ExternalSearchHistory": query});
+        with_retries(options, true, || send::<Result<SearchResponse, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `external-get-entry` remote RPC call
+    pub async fn external_get_entry_remote_rpc(target: &Address, id: String, options: &CallOptions) -> SendResult<Result<LogEntry, AppError>> {
+        let request = json!({"ExternalGetEntry": id});
+        with_retries(options, true, || send::<Result<LogEntry, AppError>>(&request, target, options.timeout)).await
     }
     
     /// Generated stub for `external-clear-history` remote RPC call
-    pub async fn external_clear_history_remote_rpc(target: &Address) -> SendResult<SuccessResponse> {
+    pub async fn external_clear_history_remote_rpc(target: &Address, options: &CallOptions) -> SendResult<Result<ClearResponse, AppError>> {
         let request = json!({"ExternalClearHistory" : {}});
-        send::<SuccessResponse>(&request, target, 30).await
+        with_retries(options, false, || send::<Result<ClearResponse, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `external-undo-clear` remote RPC call
+    pub async fn external_undo_clear_remote_rpc(target: &Address, options: &CallOptions) -> SendResult<Result<SuccessResponse, AppError>> {
+        let request = json!({"ExternalUndoClear" : {}});
+        with_retries(options, false, || send::<Result<SuccessResponse, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `external-get-config` remote RPC call
+    pub async fn external_get_config_remote_rpc(target: &Address, options: &CallOptions) -> SendResult<Result<AppConfig, AppError>> {
+        let request = json!({"ExternalGetConfig" : {}});
+        with_retries(options, true, || send::<Result<AppConfig, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `external-set-config` remote RPC call
+    pub async fn external_set_config_remote_rpc(target: &Address, update: ConfigUpdate, options: &CallOptions) -> SendResult<Result<AppConfig, AppError>> {
+        let request = json!({"ExternalSetConfig": update});
+        with_retries(options, true, || send::<Result<AppConfig, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `external-import-history` remote RPC call
+    pub async fn external_import_history_remote_rpc(target: &Address, source: ImportSource, options: &CallOptions) -> SendResult<Result<ImportResponse, AppError>> {
+        let request = json!({"ExternalImportHistory": source});
+        with_retries(options, true, || send::<Result<ImportResponse, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `external-import-history` local RPC call
+    pub async fn external_import_history_local_rpc(target: &Address, source: ImportSource, options: &CallOptions) -> SendResult<Result<ImportResponse, AppError>> {
+        let request = json!({"ExternalImportHistory": source});
+        with_retries(options, true, || send::<Result<ImportResponse, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `terminal-command` local RPC call
+    pub async fn terminal_command_local_rpc(target: &Address, command: String, options: &CallOptions) -> SendResult<Result<String, AppError>> {
+        let request = json!({"TerminalCommand": command});
+        with_retries(options, false, || send::<Result<String, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `flush-forwarding` local RPC call
+    pub async fn flush_forwarding_local_rpc(target: &Address, options: &CallOptions) -> SendResult<Result<u64, AppError>> {
+        let request = json!({"FlushForwarding" : {}});
+        with_retries(options, false, || send::<Result<u64, AppError>>(&request, target, options.timeout)).await
     }
     
     /// Generated stub for `log-external-message` remote RPC call
-    pub async fn log_external_message_remote_rpc(target: &Address, message_type: String, content: String) -> SendResult<SuccessResponse> {
-        let request = json!({"LogExternalMessage": (message_type, content)});
-        send::<SuccessResponse>(&request, target, 30).await
+    pub async fn log_external_message_remote_rpc(target: &Address, message_type: String, content: String, severity: Option<Severity>, attributes: Vec<(String, String)>, options: &CallOptions) -> SendResult<Result<SuccessResponse, AppError>> {
+        let request = json!({"LogExternalMessage": (message_type, content, severity, attributes)});
+        with_retries(options, false, || send::<Result<SuccessResponse, AppError>>(&request, target, options.timeout)).await
     }
     
     /// Generated stub for `log-external-message` local RPC call
-    pub async fn log_external_message_local_rpc(target: &Address, message_type: String, content: String) -> SendResult<SuccessResponse> {
-        let request = json!({"LogExternalMessage": (message_type, content)});
-        send::<SuccessResponse>(&request, target, 30).await
+    pub async fn log_external_message_local_rpc(target: &Address, message_type: String, content: String, severity: Option<Severity>, attributes: Vec<(String, String)>, options: &CallOptions) -> SendResult<Result<SuccessResponse, AppError>> {
+        let request = json!({"LogExternalMessage": (message_type, content, severity, attributes)});
+        with_retries(options, false, || send::<Result<SuccessResponse, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `log-external-batch` remote RPC call
+    pub async fn log_external_batch_remote_rpc(target: &Address, messages: Vec<ExternalLogMessage>, options: &CallOptions) -> SendResult<Result<BatchResponse, AppError>> {
+        // Resending is only safe when the target can recognise messages it already stored
+        let idempotent = messages.iter().all(|message| message.id.is_some());
+        let request = json!({"LogExternalBatch": messages});
+        with_retries(options, idempotent, || send::<Result<BatchResponse, AppError>>(&request, target, options.timeout)).await
+    }
+    
+    /// Generated stub for `log-external-batch` local RPC call
+    pub async fn log_external_batch_local_rpc(target: &Address, messages: Vec<ExternalLogMessage>, options: &CallOptions) -> SendResult<Result<BatchResponse, AppError>> {
+        // Resending is only safe when the target can recognise messages it already stored
+        let idempotent = messages.iter().all(|message| message.id.is_some());
+        let request = json!({"LogExternalBatch": messages});
+        with_retries(options, idempotent, || send::<Result<BatchResponse, AppError>>(&request, target, options.timeout)).await
     }
     
     
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let options = CallOptions::new().backoff(100, 1_000);
+        let waits: Vec<u64> = (0..6).map(|attempt| options.backoff_for(attempt)).collect();
+        assert_eq!(waits, vec![100, 200, 400, 800, 1_000, 1_000]);
+    }
+
+    #[test]
+    fn backoff_does_not_overflow_on_late_attempts() {
+        let options = CallOptions::new().backoff(u64::MAX / 2, u64::MAX);
+        assert_eq!(options.backoff_for(40), u64::MAX);
+        assert_eq!(CallOptions::new().backoff_for(u32::MAX), 10_000);
+    }
+
+    #[test]
+    fn non_idempotent_calls_only_retry_when_offline() {
+        let options = CallOptions::new().retry_on_deserialization_error(true);
+        assert!(options.is_retryable(&SendResult::<()>::Offline, false));
+        assert!(!options.is_retryable(&SendResult::<()>::Timeout, false));
+        assert!(!options.is_retryable(&SendResult::<()>::DeserializationError(String::new()), false));
+        assert!(!options.is_retryable(&SendResult::Success(()), false));
+    }
+
+    #[test]
+    fn idempotent_calls_follow_the_options() {
+        let options = CallOptions::new();
+        assert!(options.is_retryable(&SendResult::<()>::Timeout, true));
+        assert!(options.is_retryable(&SendResult::<()>::Offline, true));
+        assert!(!options.is_retryable(&SendResult::<()>::DeserializationError(String::new()), true));
+
+        let options = options.retry_on_timeout(false).retry_on_offline(false).retry_on_deserialization_error(true);
+        assert!(!options.is_retryable(&SendResult::<()>::Timeout, true));
+        assert!(!options.is_retryable(&SendResult::<()>::Offline, true));
+        assert!(options.is_retryable(&SendResult::<()>::DeserializationError(String::new()), true));
+    }
+
+    #[test]
+    fn http_errors_retry_only_when_the_server_is_unavailable() {
+        let error = |status| HttpSendResult::<()>::HttpError { status, body: String::new() };
+        let options = CallOptions::new();
+        assert!(options.is_retryable(&error(503), true));
+        assert!(options.is_retryable(&error(502), true));
+        assert!(!options.is_retryable(&error(503), false));
+        assert!(!options.is_retryable(&error(401), true));
+        assert!(!options.is_retryable(&error(500), true));
+        assert!(!options.clone().retry_on_unavailable(false).is_retryable(&error(503), true));
+    }
+
+    #[test]
+    fn headers_accumulate() {
+        let options = CallOptions::new().header("Cookie", "a=1").header("X-Trace", "7").header("Cookie", "a=2");
+        assert_eq!(options.headers.len(), 2);
+        assert_eq!(options.headers["Cookie"], "a=2");
+    }
+}
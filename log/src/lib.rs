@@ -0,0 +1,46 @@
+//! `log` terminal script: sends its whole argument string to message-log-app as a
+//! terminal command and prints the reply, e.g. `log tail 20` or `log grep timeout`.
+
+use hyperware_process_lib::{script, Address, Request};
+use serde_json::{json, Value};
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "message-log-app-dot-os-v0",
+    generate_unused_types: true,
+});
+
+/// The app commands are sent to, on our own node
+const APP_PROCESS: &str = "message-log-app:message-log-app:template.os";
+
+/// Seconds to wait for the app to answer
+const TIMEOUT_SECS: u64 = 10;
+
+script!(init);
+fn init(our: Address, args: String) -> String {
+    let app = Address::new(our.node(), APP_PROCESS.parse().expect("APP_PROCESS is a valid process id"));
+    let body = json!({"TerminalCommand": args.trim()});
+
+    let response = match Request::to(app).body(body.to_string().into_bytes()).send_and_await_response(TIMEOUT_SECS) {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => return format!("message-log-app did not answer: {:?}", e.kind),
+        Err(e) => return format!("Failed to send to message-log-app: {}", e),
+    };
+
+    // The app answers with a JSON `Result<String, AppError>`
+    match serde_json::from_slice::<Result<String, Value>>(response.body()) {
+        Ok(Ok(output)) => output,
+        Ok(Err(error)) => format!("message-log-app: {}", error_message(&error)),
+        Err(e) => format!("Unexpected reply from message-log-app: {}", e),
+    }
+}
+
+// Helper function to pull the message out of a serialized `AppError`, e.g. `{"BadRequest": "..."}`
+fn error_message(error: &Value) -> String {
+    error
+        .as_object()
+        .and_then(|variant| variant.values().next())
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .unwrap_or_else(|| error.to_string())
+}
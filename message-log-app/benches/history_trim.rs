@@ -0,0 +1,77 @@
+//! Cost per insert of keeping the newest N entries in memory, the way the app used
+//! to (shift a `Vec` down on every insert) against the ring buffer it uses now.
+//!
+//! This compares the two buffers and nothing else. It says nothing about how fast
+//! the app logs: every insert also goes through SQLite, which needs a running node.
+//!
+//! Run with `cargo bench -p message-log-app --bench history_trim`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+#[allow(dead_code)]
+#[path = "../src/ring.rs"]
+mod ring;
+
+use ring::RingBuffer;
+
+/// History sizes to measure at
+const HISTORY_SIZES: &[usize] = &[10_000, 50_000, 100_000];
+
+/// Entries logged per run, after the history is already full
+const INSERTS: usize = 20_000;
+
+/// Roughly the shape of a stored entry
+#[derive(Clone)]
+struct Entry {
+    seq: u64,
+    source: String,
+    content: String,
+}
+
+fn entry(seq: u64) -> Entry {
+    Entry {
+        seq,
+        source: "bench:message-log-app:template.os".to_string(),
+        content: format!("Benchmark message {}", seq),
+    }
+}
+
+// Helper function to time `INSERTS` inserts into a full history of `size` entries
+fn run<F: FnMut(Entry)>(size: usize, mut insert: F) -> Duration {
+    for seq in 0..size as u64 {
+        insert(entry(seq));
+    }
+    let start = Instant::now();
+    for seq in size as u64..(size + INSERTS) as u64 {
+        insert(entry(seq));
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, size: usize, elapsed: Duration) {
+    let per_insert = elapsed.as_nanos() as f64 / INSERTS as f64;
+    println!("{:<12} history={:<8} {:>10.1} ms {:>10.0} ns/insert", name, size, elapsed.as_secs_f64() * 1_000.0, per_insert);
+}
+
+fn main() {
+    println!("In-memory buffer cost only; not a measure of logging throughput");
+    for &size in HISTORY_SIZES {
+        let mut history: Vec<Entry> = Vec::with_capacity(size + 1);
+        let elapsed = run(size, |entry| {
+            history.push(entry);
+            if history.len() > size {
+                history.remove(0);
+            }
+        });
+        black_box(history.last().map(|entry| entry.seq));
+        report("vec", size, elapsed);
+
+        let mut history = RingBuffer::with_capacity(size);
+        let elapsed = run(size, |entry| {
+            black_box(history.push(entry));
+        });
+        black_box(history.iter().next_back().map(|entry| (entry.seq, entry.source.len(), entry.content.len())));
+        report("ring_buffer", size, elapsed);
+    }
+}
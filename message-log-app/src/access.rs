@@ -0,0 +1,299 @@
+use std::fmt;
+use hyperware_app_common::get_server;
+use hyperware_process_lib::{
+    http::server::{HttpBindingConfig, WsBindingConfig},
+    logging::error,
+    our, Address, ProcessId,
+};
+use crate::types::{AccessPolicy, AppError, MessageChannel, MessageType, Severity};
+use crate::{log_structured, AppState};
+
+/// WebSocket binding covered by `AccessPolicy::http_auth`
+pub const WS_PATH: &str = "/";
+
+/// Allowlist entry for HTTP and WebSocket clients with a logged-in session
+pub const HTTP_ENTRY: &str = "http";
+
+/// Allowlist entry for HTTP and WebSocket clients while `http_auth` is off
+pub const ANONYMOUS_ENTRY: &str = "anonymous";
+
+/// Allowlist entry for every process on our node
+pub const LOCAL_ENTRY: &str = "local";
+
+/// Allowlist entry for every process on other nodes
+pub const REMOTE_ENTRY: &str = "*";
+
+/// The `log` terminal script, allowed everything by the default policy
+pub const TERMINAL_SCRIPT: &str = "log:message-log-app:template.os";
+
+/// Source of the entries logged for denied requests
+pub const ACCESS_CONTROL_SOURCE: &str = "AccessControl";
+
+/// HTTP bindings covered by `AccessPolicy::http_auth`
+pub const API_PATHS: &[&str] = &[
+    "/api/status",
+    "/api/stats",
+    "/api/history",
+    "/api/search",
+    "/api/entry",
+    "/api/log",
+    "/api/batch",
+    "/api/clear-history",
+    "/api/undo-clear",
+    "/api/config",
+    "/api/export",
+    "/api/download",
+    "/api/import",
+];
+
+/// Operation class a handler belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Admin,
+}
+
+/// Whoever made the request being checked
+#[derive(Debug, Clone)]
+pub enum Caller {
+    /// A process, local or on another node
+    Process(Address),
+    /// An HTTP or WebSocket client with a logged-in session
+    Http,
+    /// An HTTP or WebSocket client on a binding that does not require a session
+    Anonymous,
+}
+
+impl fmt::Display for Caller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Caller::Process(address) => write!(f, "{}", address),
+            Caller::Http => write!(f, "{}", HTTP_ENTRY),
+            Caller::Anonymous => write!(f, "{}", ANONYMOUS_ENTRY),
+        }
+    }
+}
+
+impl AccessPolicy {
+    /// Whether `caller` may perform operations of class `access`
+    pub fn allows(&self, caller: &Caller, access: Access) -> bool {
+        self.allows_for(&our(), caller, access)
+    }
+
+    // Helper function to check `caller` as the app running at `our`
+    fn allows_for(&self, our: &Address, caller: &Caller, access: Access) -> bool {
+        // The app can never lock itself out; every other caller needs an entry
+        if let Caller::Process(address) = caller {
+            if address == our {
+                return true;
+            }
+        }
+
+        let allowlist = match access {
+            Access::Read => &self.read,
+            Access::Write => &self.write,
+            Access::Admin => &self.admin,
+        };
+        allowlist.iter().any(|allowed| match caller {
+            Caller::Http => allowed == HTTP_ENTRY,
+            Caller::Anonymous => allowed == ANONYMOUS_ENTRY,
+            // `*` covers other nodes only; local processes are `local` or listed by id
+            Caller::Process(address) if allowed == REMOTE_ENTRY => address.node() != our.node(),
+            Caller::Process(address) if allowed == LOCAL_ENTRY => address.node() == our.node(),
+            Caller::Process(address) if allowed.contains('@') => *allowed == address.to_string(),
+            Caller::Process(address) if allowed.contains(':') => {
+                address.node() == our.node() && *allowed == address.process.to_string()
+            }
+            Caller::Process(address) => allowed == address.node(),
+        })
+    }
+
+    /// Reject allowlist entries that could never match anything
+    pub fn validate(&self) -> Result<(), AppError> {
+        for entry in self.read.iter().chain(&self.write).chain(&self.admin) {
+            if entry.trim().is_empty() || entry.trim() != entry {
+                return Err(AppError::BadRequest(format!("Invalid access policy entry: {:?}", entry)));
+            }
+            if entry.contains('@') && entry.parse::<Address>().is_err() {
+                return Err(AppError::BadRequest(format!("Invalid address in access policy: {}", entry)));
+            }
+            if !entry.contains('@') && entry.contains(':') && entry.parse::<ProcessId>().is_err() {
+                return Err(AppError::BadRequest(format!("Invalid process id in access policy: {}", entry)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AppState {
+    /// Caller of an HTTP or WebSocket handler; with auth on, only the node owner can get through
+    pub fn http_caller(&self) -> Caller {
+        if self.config.access.http_auth {
+            Caller::Http
+        } else {
+            Caller::Anonymous
+        }
+    }
+
+    /// Check `caller` against the access policy, logging the attempt if it is denied
+    pub fn authorize(&mut self, caller: &Caller, access: Access, operation: &str) -> Result<(), AppError> {
+        if self.config.access.allows(caller, access) {
+            return Ok(());
+        }
+
+        let result = log_structured(
+            self,
+            ACCESS_CONTROL_SOURCE.to_string(),
+            MessageChannel::Internal,
+            MessageType::Other("AccessDenied".to_string()),
+            Some(format!("{} denied {:?} access for {}", caller, access, operation)),
+            Severity::Warn,
+            vec![
+                ("caller".to_string(), caller.to_string()),
+                ("operation".to_string(), operation.to_string()),
+                ("access".to_string(), format!("{:?}", access).to_lowercase()),
+            ],
+        );
+        if let Err(e) = result {
+            error!("Failed to log denied {} by {}: {}", operation, caller, e);
+        }
+
+        Err(AppError::Forbidden(format!("{} may not {}", caller, operation)))
+    }
+
+    /// Check the caller of an HTTP handler
+    pub fn authorize_http(&mut self, access: Access, operation: &str) -> Result<(), AppError> {
+        let caller = self.http_caller();
+        self.authorize(&caller, access, operation)
+    }
+
+    /// Rebind the `/api/*` paths and the WebSocket so they match `http_auth`
+    pub fn apply_http_auth(&self) {
+        let Some(server) = get_server() else {
+            return;
+        };
+        let authenticated = self.config.access.http_auth;
+        let config = HttpBindingConfig::new(authenticated, false, false, None);
+        for path in API_PATHS {
+            if let Err(e) = server.modify_http_path(path, config.clone()) {
+                error!("Failed to rebind {}: {:?}", path, e);
+            }
+        }
+        if let Err(e) = server.modify_ws_path(WS_PATH, WsBindingConfig::new(authenticated, false, false)) {
+            error!("Failed to rebind WebSocket {}: {:?}", WS_PATH, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP: &str = "our.os@message-log-app:message-log-app:template.os";
+
+    fn address(address: &str) -> Address {
+        address.parse().unwrap()
+    }
+
+    fn process(address_str: &str) -> Caller {
+        Caller::Process(address(address_str))
+    }
+
+    fn policy(write: &[&str]) -> AccessPolicy {
+        AccessPolicy {
+            read: Vec::new(),
+            write: write.iter().map(|entry| entry.to_string()).collect(),
+            admin: Vec::new(),
+            http_auth: true,
+        }
+    }
+
+    fn allows(policy: &AccessPolicy, caller: &Caller) -> bool {
+        policy.allows_for(&address(APP), caller, Access::Write)
+    }
+
+    #[test]
+    fn the_app_is_always_allowed() {
+        assert!(allows(&policy(&[]), &process(APP)));
+        assert!(!allows(&policy(&[]), &process("our.os@other:pkg:pub.os")));
+    }
+
+    #[test]
+    fn star_covers_other_nodes_and_local_covers_ours() {
+        let remote = policy(&["*"]);
+        assert!(allows(&remote, &process("alice.os@logger:pkg:pub.os")));
+        assert!(!allows(&remote, &process("our.os@logger:pkg:pub.os")));
+        assert!(!allows(&remote, &Caller::Http));
+
+        let local = policy(&["local"]);
+        assert!(allows(&local, &process("our.os@logger:pkg:pub.os")));
+        assert!(!allows(&local, &process("alice.os@logger:pkg:pub.os")));
+    }
+
+    #[test]
+    fn node_entries_cover_every_process_on_that_node() {
+        let policy = policy(&["alice.os"]);
+        assert!(allows(&policy, &process("alice.os@logger:pkg:pub.os")));
+        assert!(allows(&policy, &process("alice.os@other:pkg:pub.os")));
+        assert!(!allows(&policy, &process("bob.os@logger:pkg:pub.os")));
+    }
+
+    #[test]
+    fn process_ids_only_cover_our_node() {
+        let policy = policy(&["logger:pkg:pub.os"]);
+        assert!(allows(&policy, &process("our.os@logger:pkg:pub.os")));
+        assert!(!allows(&policy, &process("alice.os@logger:pkg:pub.os")));
+        assert!(!allows(&policy, &process("our.os@other:pkg:pub.os")));
+    }
+
+    #[test]
+    fn full_addresses_cover_exactly_that_process() {
+        let policy = policy(&["alice.os@logger:pkg:pub.os"]);
+        assert!(allows(&policy, &process("alice.os@logger:pkg:pub.os")));
+        assert!(!allows(&policy, &process("alice.os@other:pkg:pub.os")));
+        assert!(!allows(&policy, &process("our.os@logger:pkg:pub.os")));
+    }
+
+    #[test]
+    fn http_and_anonymous_are_separate_callers() {
+        let http = policy(&["http"]);
+        assert!(allows(&http, &Caller::Http));
+        assert!(!allows(&http, &Caller::Anonymous));
+        assert!(!allows(&http, &process("alice.os@http:pkg:pub.os")));
+
+        let anonymous = policy(&["anonymous"]);
+        assert!(allows(&anonymous, &Caller::Anonymous));
+        assert!(!allows(&anonymous, &Caller::Http));
+    }
+
+    #[test]
+    fn default_policy_lets_anyone_write_but_only_the_owner_read_and_administer() {
+        let policy = AccessPolicy::default();
+        let our = address(APP);
+        let remote = process("alice.os@logger:pkg:pub.os");
+        let local = process("our.os@logger:pkg:pub.os");
+        let terminal = process(&format!("our.os@{}", TERMINAL_SCRIPT));
+        for caller in [&remote, &local, &terminal, &Caller::Http] {
+            assert!(policy.allows_for(&our, caller, Access::Write), "{} may write", caller);
+        }
+        for access in [Access::Read, Access::Admin] {
+            assert!(policy.allows_for(&our, &Caller::Http, access));
+            assert!(policy.allows_for(&our, &terminal, access));
+            assert!(!policy.allows_for(&our, &remote, access));
+            assert!(!policy.allows_for(&our, &local, access));
+            assert!(!policy.allows_for(&our, &Caller::Anonymous, access));
+        }
+        assert!(policy.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_entries_that_cannot_match() {
+        for entry in ["*", "local", "http", "anonymous", "alice.os", "logger:pkg:pub.os", "alice.os@logger:pkg:pub.os"] {
+            assert!(policy(&[entry]).validate().is_ok(), "{} is valid", entry);
+        }
+        for entry in ["", " alice.os", "alice.os ", "logger:pkg", "alice.os@logger", "a:b:c:d"] {
+            assert!(policy(&[entry]).validate().is_err(), "{:?} is invalid", entry);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use hyperware_process_lib::{
+    our,
+    sqlite::Sqlite,
+    vfs::{create_drive, create_file, open_dir},
+};
+use crate::db;
+use crate::types::{HistoryQuery, LogEntry};
+
+/// VFS drive holding everything the app writes to files
+const DRIVE_NAME: &str = "logs";
+
+/// Directory on the drive for the copies taken before each clear
+const CLEARS_DIR: &str = "clears";
+
+/// Path of the app's drive, creating it on first use
+pub fn drive_path() -> anyhow::Result<String> {
+    Ok(create_drive(our().package_id(), DRIVE_NAME, None)?)
+}
+
+/// Path of a directory on the app's drive, creating it on first use
+pub fn dir_path(name: &str) -> anyhow::Result<String> {
+    let path = format!("{}/{}", drive_path()?, name);
+    open_dir(&path, true, None)?;
+    Ok(path)
+}
+
+/// Serialize entries as JSON Lines, one entry per line
+pub fn to_jsonl(entries: &[LogEntry]) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut bytes, entry)?;
+        bytes.push(b'\n');
+    }
+    Ok(bytes)
+}
+
+/// Copy every live entry, oldest first, into a new JSON Lines file for `clear_id`.
+/// Returns the file's path and how many entries it holds.
+pub fn archive_live_entries(db: &Sqlite, clear_id: u64) -> anyhow::Result<(String, u64)> {
+    let path = format!("{}/clear-{}.jsonl", dir_path(CLEARS_DIR)?, clear_id);
+    let mut file = create_file(&path, None)?;
+
+    let mut query = HistoryQuery {
+        since_seq: Some(0),
+        limit: Some(u64::MAX),
+        ..HistoryQuery::default()
+    };
+    let mut archived = 0;
+    loop {
+        let (entries, next_cursor) = db::query_entries(db, &query)?;
+        file.append(&to_jsonl(&entries)?)?;
+        archived += entries.len() as u64;
+        match next_cursor {
+            Some(seq) => query.since_seq = Some(seq),
+            None => break,
+        }
+    }
+    file.sync_all()?;
+
+    Ok((path, archived))
+}
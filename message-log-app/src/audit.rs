@@ -0,0 +1,72 @@
+use std::time::Instant;
+use hyperware_app_common::APP_HELPERS;
+use hyperware_process_lib::{logging::error, our};
+use crate::access::{Access, Caller};
+use crate::types::{AppError, MessageChannel, MessageType, Severity};
+use crate::{log_structured, AppState};
+
+/// Start of the source of every audit entry, followed by the caller
+pub const AUDIT_SOURCE_PREFIX: &str = "Request:";
+
+// Size in bytes of the request being handled: its body plus any blob attached to it
+fn body_size() -> u64 {
+    APP_HELPERS.with(|helpers| {
+        let helpers = helpers.borrow();
+        let Some(message) = helpers.current_message.as_ref() else {
+            return 0;
+        };
+        let blob = message.blob().map_or(0, |blob| blob.bytes.len());
+        (message.body().len() + blob) as u64
+    })
+}
+
+impl AppState {
+    /// Run a local or remote handler like `instrumented`, checking the sender against
+    /// `access` first, then log who called it, how big the request was, how long it took
+    /// and how it went. `body` gets the caller.
+    pub fn audited<T>(
+        &mut self,
+        handler: &str,
+        access: Option<Access>,
+        body: impl FnOnce(&mut Self, &Caller) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let caller = &Caller::Process(self.get_source().clone());
+        let body_size = body_size();
+        let started = Instant::now();
+        let result = self.instrumented(handler, |state| {
+            if let Some(access) = access {
+                state.authorize(caller, access, handler)?;
+            }
+            body(state, caller)
+        });
+        let latency_ms = started.elapsed().as_secs_f64() * 1_000.0;
+
+        let local = matches!(caller, Caller::Process(address) if address.node() == our().node());
+        let message_type = if local { MessageType::LocalRequest } else { MessageType::RemoteRequest };
+        let (outcome, severity) = match &result {
+            Ok(_) => ("ok", Severity::Info),
+            Err(e) => (e.code(), Severity::Warn),
+        };
+
+        let logged = log_structured(
+            self,
+            format!("{}{}", AUDIT_SOURCE_PREFIX, caller),
+            MessageChannel::External,
+            message_type,
+            Some(format!("{} from {}: {} in {:.1} ms", handler, caller, outcome, latency_ms)),
+            severity,
+            vec![
+                ("source".to_string(), caller.to_string()),
+                ("method".to_string(), handler.to_string()),
+                ("body_size".to_string(), body_size.to_string()),
+                ("latency_ms".to_string(), format!("{:.3}", latency_ms)),
+                ("outcome".to_string(), outcome.to_string()),
+            ],
+        );
+        if let Err(e) = logged {
+            error!("Failed to log {} request from {}: {}", handler, caller, e);
+        }
+
+        result
+    }
+}
@@ -0,0 +1,762 @@
+use std::collections::{HashMap, HashSet};
+use hyperware_process_lib::{our, sqlite::{self, Sqlite}};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use crate::history;
+use crate::types::{
+    ExternalLogMessage, HistoryQuery, LogEntry, MessageChannel, MessageType, SearchHit, SearchQuery, Severity,
+};
+
+/// Name of the SQLite database holding the log
+const DB_NAME: &str = "message_log";
+
+/// Page size used when a query does not set `limit`
+const DEFAULT_PAGE_SIZE: u64 = 100;
+
+/// Upper bound on the page size a single query may request
+const MAX_PAGE_SIZE: u64 = 1_000;
+
+/// Tokens of context a search snippet shows around the matches
+const SNIPPET_TOKENS: u64 = 16;
+
+/// Most ids looked up by a single `existing_ids` statement
+const ID_LOOKUP_CHUNK: usize = 500;
+
+/// Setting holding the seq after the newest entry ever stored; saved state can lag it
+pub const NEXT_SEQ_SETTING: &str = "next_seq";
+
+/// Statement writing one setting, shared by `save_setting` and `insert_entries`
+const SAVE_SETTING: &str = "INSERT OR REPLACE INTO settings (key, value) VALUES (?, ?);";
+
+/// Statement adding to one channel's count
+const ADD_CHANNEL_COUNT: &str = "INSERT INTO channel_counts (channel, n) VALUES (?, ?) \
+    ON CONFLICT (channel) DO UPDATE SET n = n + excluded.n;";
+
+/// Statements run on every open; all of them are idempotent
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS log_entries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        source TEXT NOT NULL,
+        channel TEXT NOT NULL,
+        type_name TEXT NOT NULL,
+        content TEXT,
+        timestamp INTEGER NOT NULL
+    );",
+    "CREATE INDEX IF NOT EXISTS idx_log_entries_timestamp ON log_entries (timestamp);",
+    "CREATE INDEX IF NOT EXISTS idx_log_entries_channel ON log_entries (channel);",
+    "CREATE INDEX IF NOT EXISTS idx_log_entries_source ON log_entries (source);",
+    "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL);",
+];
+
+/// Schema changes applied in order on top of `SCHEMA`; never edit a shipped entry, append a new one
+const MIGRATIONS: &[&[&str]] = &[
+    // 1: severity levels and structured attributes
+    &[
+        "ALTER TABLE log_entries ADD COLUMN severity INTEGER NOT NULL DEFAULT 2;",
+        "ALTER TABLE log_entries ADD COLUMN attributes TEXT NOT NULL DEFAULT '[]';",
+        "CREATE INDEX IF NOT EXISTS idx_log_entries_severity ON log_entries (severity);",
+    ],
+    // 2: stable ids and sequence numbers; older rows get random ids and seq = rowid
+    &[
+        "ALTER TABLE log_entries ADD COLUMN uuid TEXT;",
+        "ALTER TABLE log_entries ADD COLUMN seq INTEGER;",
+        "UPDATE log_entries SET uuid = lower(hex(randomblob(16))), seq = id WHERE uuid IS NULL;",
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_log_entries_uuid ON log_entries (uuid);",
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_log_entries_seq ON log_entries (seq);",
+    ],
+    // 3: soft deletes; a cleared row carries the id of the clear until it is purged
+    &[
+        "ALTER TABLE log_entries ADD COLUMN cleared_in INTEGER;",
+        "CREATE INDEX IF NOT EXISTS idx_log_entries_cleared_in ON log_entries (cleared_in);",
+    ],
+    // 4: settings written through on change instead of saving the whole state every message
+    &[
+        "CREATE TABLE IF NOT EXISTS settings (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+    ],
+    // 5: full-text index over content, source and type name, kept in sync by triggers
+    &[
+        "CREATE VIRTUAL TABLE IF NOT EXISTS log_search USING fts5(
+            content, source, type_name,
+            content = 'log_entries', content_rowid = 'id'
+        );",
+        "CREATE TRIGGER IF NOT EXISTS log_search_insert AFTER INSERT ON log_entries BEGIN
+            INSERT INTO log_search (rowid, content, source, type_name)
+            VALUES (new.id, new.content, new.source, new.type_name);
+        END;",
+        "CREATE TRIGGER IF NOT EXISTS log_search_delete AFTER DELETE ON log_entries BEGIN
+            INSERT INTO log_search (log_search, rowid, content, source, type_name)
+            VALUES ('delete', old.id, old.content, old.source, old.type_name);
+        END;",
+        "CREATE TRIGGER IF NOT EXISTS log_search_update AFTER UPDATE OF content, source, type_name ON log_entries BEGIN
+            INSERT INTO log_search (log_search, rowid, content, source, type_name)
+            VALUES ('delete', old.id, old.content, old.source, old.type_name);
+            INSERT INTO log_search (rowid, content, source, type_name)
+            VALUES (new.id, new.content, new.source, new.type_name);
+        END;",
+        "INSERT INTO log_search (log_search) VALUES ('rebuild');",
+    ],
+    // 6: rows written before typed message types stored `Other("X")`; rewrite them to `Other:X`
+    &[
+        r#"UPDATE log_entries SET type_name = 'Other:' || replace(replace(replace(
+            substr(type_name, 8, length(type_name) - 9), '\\', char(1)), '\"', '"'), char(1), '\')
+        WHERE type_name LIKE 'Other("%")';"#,
+    ],
+    // 7: entries waiting for the forwarding target, oldest first, kept until the target acks them
+    &[
+        "CREATE TABLE IF NOT EXISTS forward_queue (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            message TEXT NOT NULL
+        );",
+    ],
+    // 8: per-channel message counts, bumped with each insert instead of rewriting one setting;
+    // seeded from that setting, and the rolling stats go back to the saved state
+    &[
+        "CREATE TABLE IF NOT EXISTS channel_counts (
+            channel TEXT PRIMARY KEY,
+            n INTEGER NOT NULL
+        );",
+        "INSERT OR IGNORE INTO channel_counts (channel, n)
+            SELECT json_extract(value, '$[0]'), json_extract(value, '$[1]')
+            FROM json_each((SELECT value FROM settings WHERE key = 'message_counts'));",
+        "DELETE FROM settings WHERE key IN ('message_counts', 'stats');",
+    ],
+];
+
+/// Columns every entry query selects, in `row_to_entry` order
+const ENTRY_COLUMNS: &str = "uuid, seq, source, channel, type_name, content, timestamp, severity, attributes";
+
+/// Open (or create) the log database and make sure the schema exists
+pub fn open() -> anyhow::Result<Sqlite> {
+    let db = sqlite::open(our().package_id(), DB_NAME, None)?;
+    for statement in SCHEMA {
+        db.write(statement.to_string(), vec![], None)?;
+    }
+    migrate(&db)?;
+    Ok(db)
+}
+
+// Apply every migration newer than the recorded schema version, each in its own transaction
+fn migrate(db: &Sqlite) -> anyhow::Result<()> {
+    let rows = db.read("SELECT MAX(version) AS version FROM schema_version;".to_string(), vec![])?;
+    let current = rows
+        .first()
+        .and_then(|row| row.get("version"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as usize;
+
+    for (index, statements) in MIGRATIONS.iter().enumerate().skip(current) {
+        let tx_id = db.begin_tx()?;
+        for statement in *statements {
+            db.write(statement.to_string(), vec![], Some(tx_id))?;
+        }
+        db.write(
+            "INSERT INTO schema_version (version) VALUES (?);".to_string(),
+            vec![Value::from(index as u64 + 1)],
+            Some(tx_id),
+        )?;
+        db.commit_tx(tx_id)?;
+    }
+    Ok(())
+}
+
+/// Append entries to the log all-or-nothing. The multi-row INSERT, the channel counts
+/// and the new `NEXT_SEQ_SETTING` high-water mark commit in one transaction.
+pub fn insert_entries(db: &Sqlite, entries: &[LogEntry]) -> anyhow::Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    
+    let rows = vec!["(?, ?, ?, ?, ?, ?, ?, ?, ?)"; entries.len()].join(", ");
+    let statement = format!(
+        "INSERT INTO log_entries \
+        (uuid, seq, source, channel, type_name, content, timestamp, severity, attributes) \
+        VALUES {};",
+        rows,
+    );
+    let mut params = Vec::with_capacity(entries.len() * 9);
+    for entry in entries {
+        params.extend([
+            Value::String(entry.id.clone()),
+            Value::from(entry.seq),
+            Value::String(entry.source.clone()),
+            Value::String(channel_key(&entry.channel)),
+            Value::String(type_key(&entry.type_name)),
+            entry.content.clone().map(Value::String).unwrap_or(Value::Null),
+            Value::from(entry.timestamp),
+            Value::from(severity_level(entry.severity)),
+            Value::String(serde_json::to_string(&entry.attributes)?),
+        ]);
+    }
+    let next_seq = entries.iter().map(|entry| entry.seq).max().unwrap_or(0) + 1;
+    
+    let tx_id = db.begin_tx()?;
+    db.write(statement, params, Some(tx_id))?;
+    for (channel, count) in count_channels(entries) {
+        db.write(ADD_CHANNEL_COUNT.to_string(), vec![Value::String(channel_key(&channel)), Value::from(count)], Some(tx_id))?;
+    }
+    db.write(
+        SAVE_SETTING.to_string(),
+        vec![Value::String(NEXT_SEQ_SETTING.to_string()), Value::String(serde_json::to_string(&next_seq)?)],
+        Some(tx_id),
+    )?;
+    db.commit_tx(tx_id)
+}
+
+/// Stored message counts by channel
+pub fn channel_counts(db: &Sqlite) -> anyhow::Result<Vec<(MessageChannel, usize)>> {
+    let rows = db.read("SELECT channel, n FROM channel_counts ORDER BY channel;".to_string(), vec![])?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let channel = parse_channel(row.get("channel")?.as_str()?);
+            let count = row.get("n")?.as_u64()?;
+            Some((channel, count as usize))
+        })
+        .collect())
+}
+
+/// Add `counts` to the stored channel counts, e.g. when a clear is undone
+pub fn add_channel_counts(db: &Sqlite, counts: &[(MessageChannel, usize)]) -> anyhow::Result<()> {
+    let tx_id = db.begin_tx()?;
+    for (channel, count) in counts {
+        db.write(ADD_CHANNEL_COUNT.to_string(), vec![Value::String(channel_key(channel)), Value::from(*count)], Some(tx_id))?;
+    }
+    db.commit_tx(tx_id)
+}
+
+/// Forget every stored channel count
+pub fn reset_channel_counts(db: &Sqlite) -> anyhow::Result<()> {
+    db.write("DELETE FROM channel_counts;".to_string(), vec![], None)
+}
+
+// Entries per channel in a batch, in order of first appearance
+fn count_channels(entries: &[LogEntry]) -> Vec<(MessageChannel, u64)> {
+    let mut counts: Vec<(MessageChannel, u64)> = Vec::new();
+    for entry in entries {
+        match counts.iter_mut().find(|(channel, _)| *channel == entry.channel) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((entry.channel, 1)),
+        }
+    }
+    counts
+}
+
+/// Delete the oldest live entries so that at most `max_entries` remain
+pub fn trim(db: &Sqlite, max_entries: u64) -> anyhow::Result<()> {
+    let statement = "DELETE FROM log_entries WHERE cleared_in IS NULL AND id <= \
+        (SELECT id FROM log_entries WHERE cleared_in IS NULL ORDER BY id DESC LIMIT 1 OFFSET ?);".to_string();
+    db.write(statement, vec![Value::from(max_entries)], None)
+}
+
+/// The oldest live entries beyond the newest `max_entries`, i.e. what `trim` would delete
+pub fn overflow_entries(db: &Sqlite, max_entries: u64) -> anyhow::Result<Vec<LogEntry>> {
+    let statement = format!(
+        "SELECT {} FROM log_entries WHERE cleared_in IS NULL AND id <= \
+        (SELECT id FROM log_entries WHERE cleared_in IS NULL ORDER BY id DESC LIMIT 1 OFFSET ?) \
+        ORDER BY id ASC;",
+        ENTRY_COLUMNS,
+    );
+    let rows = db.read(statement, vec![Value::from(max_entries)])?;
+    Ok(rows.iter().map(row_to_entry).collect())
+}
+
+/// Delete the oldest live entries of one channel so that at most `max_entries` remain
+pub fn trim_channel(db: &Sqlite, channel: &MessageChannel, max_entries: u64) -> anyhow::Result<()> {
+    let statement = "DELETE FROM log_entries WHERE cleared_in IS NULL AND channel = ?1 AND id <= \
+        (SELECT id FROM log_entries WHERE cleared_in IS NULL AND channel = ?1 \
+        ORDER BY id DESC LIMIT 1 OFFSET ?2);".to_string();
+    db.write(statement, vec![Value::String(channel_key(channel)), Value::from(max_entries)], None)
+}
+
+/// Delete live entries older than `timestamp`, optionally only on one channel
+pub fn delete_older_than(db: &Sqlite, timestamp: u64, channel: Option<&MessageChannel>) -> anyhow::Result<()> {
+    match channel {
+        Some(channel) => db.write(
+            "DELETE FROM log_entries WHERE cleared_in IS NULL AND timestamp < ? AND channel = ?;".to_string(),
+            vec![Value::from(timestamp), Value::String(channel_key(channel))],
+            None,
+        ),
+        None => db.write(
+            "DELETE FROM log_entries WHERE cleared_in IS NULL AND timestamp < ?;".to_string(),
+            vec![Value::from(timestamp)],
+            None,
+        ),
+    }
+}
+
+/// Soft-delete every live entry, tagging it with `clear_id` so it can be restored
+pub fn soft_clear(db: &Sqlite, clear_id: u64) -> anyhow::Result<()> {
+    db.write(
+        "UPDATE log_entries SET cleared_in = ? WHERE cleared_in IS NULL;".to_string(),
+        vec![Value::from(clear_id)],
+        None,
+    )
+}
+
+/// Bring back the entries soft-deleted by `clear_id`
+pub fn restore_cleared(db: &Sqlite, clear_id: u64) -> anyhow::Result<()> {
+    db.write(
+        "UPDATE log_entries SET cleared_in = NULL WHERE cleared_in = ?;".to_string(),
+        vec![Value::from(clear_id)],
+        None,
+    )
+}
+
+/// Permanently delete soft-deleted entries, except those of the clear in `keep`
+pub fn purge_cleared(db: &Sqlite, keep: Option<u64>) -> anyhow::Result<()> {
+    match keep {
+        Some(clear_id) => db.write(
+            "DELETE FROM log_entries WHERE cleared_in IS NOT NULL AND cleared_in != ?;".to_string(),
+            vec![Value::from(clear_id)],
+            None,
+        ),
+        None => db.write(
+            "DELETE FROM log_entries WHERE cleared_in IS NOT NULL;".to_string(),
+            vec![],
+            None,
+        ),
+    }
+}
+
+/// Store a setting as JSON, replacing any earlier value under `key`
+pub fn save_setting<T: Serialize>(db: &Sqlite, key: &str, value: &T) -> anyhow::Result<()> {
+    db.write(
+        SAVE_SETTING.to_string(),
+        vec![Value::String(key.to_string()), Value::String(serde_json::to_string(value)?)],
+        None,
+    )
+}
+
+/// A setting stored by `save_setting`, if there is one
+pub fn load_setting<T: DeserializeOwned>(db: &Sqlite, key: &str) -> anyhow::Result<Option<T>> {
+    let rows = db.read(
+        "SELECT value FROM settings WHERE key = ?;".to_string(),
+        vec![Value::String(key.to_string())],
+    )?;
+    match rows.first().and_then(|row| row.get("value")).and_then(Value::as_str) {
+        Some(value) => Ok(Some(serde_json::from_str(value)?)),
+        None => Ok(None),
+    }
+}
+
+/// Add messages to the end of the forwarding queue, all-or-nothing
+pub fn enqueue_forward(db: &Sqlite, messages: &[ExternalLogMessage]) -> anyhow::Result<()> {
+    if messages.is_empty() {
+        return Ok(());
+    }
+    
+    let rows = vec!["(?)"; messages.len()].join(", ");
+    let statement = format!("INSERT INTO forward_queue (message) VALUES {};", rows);
+    let mut params = Vec::with_capacity(messages.len());
+    for message in messages {
+        params.push(Value::String(serde_json::to_string(message)?));
+    }
+    db.write(statement, params, None)
+}
+
+/// The oldest `limit` queued messages with their queue ids, oldest first
+pub fn forward_batch(db: &Sqlite, limit: u64) -> anyhow::Result<Vec<(u64, ExternalLogMessage)>> {
+    let rows = db.read(
+        "SELECT id, message FROM forward_queue ORDER BY id ASC LIMIT ?;".to_string(),
+        vec![Value::from(limit)],
+    )?;
+    rows.iter()
+        .map(|row| {
+            let id = row.get("id").and_then(Value::as_u64).unwrap_or(0);
+            let message = row.get("message").and_then(Value::as_str).unwrap_or_default();
+            Ok((id, serde_json::from_str(message)?))
+        })
+        .collect()
+}
+
+/// Remove queued messages up to and including `last_id`, once the target has taken them
+pub fn dequeue_forward(db: &Sqlite, last_id: u64) -> anyhow::Result<()> {
+    db.write("DELETE FROM forward_queue WHERE id <= ?;".to_string(), vec![Value::from(last_id)], None)
+}
+
+/// Delete the oldest queued messages so that at most `max_entries` remain
+pub fn trim_forward_queue(db: &Sqlite, max_entries: u64) -> anyhow::Result<()> {
+    let statement = "DELETE FROM forward_queue WHERE id <= \
+        (SELECT id FROM forward_queue ORDER BY id DESC LIMIT 1 OFFSET ?);".to_string();
+    db.write(statement, vec![Value::from(max_entries)], None)
+}
+
+/// Empty the forwarding queue
+pub fn clear_forward_queue(db: &Sqlite) -> anyhow::Result<()> {
+    db.write("DELETE FROM forward_queue;".to_string(), vec![], None)
+}
+
+/// Number of messages waiting in the forwarding queue
+pub fn forward_queue_len(db: &Sqlite) -> anyhow::Result<u64> {
+    let rows = db.read("SELECT COUNT(*) AS count FROM forward_queue;".to_string(), vec![])?;
+    Ok(rows
+        .first()
+        .and_then(|row| row.get("count"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0))
+}
+
+/// Highest sequence number ever stored (cleared or not), 0 for an empty log
+pub fn max_seq(db: &Sqlite) -> anyhow::Result<u64> {
+    let rows = db.read("SELECT MAX(seq) AS seq FROM log_entries;".to_string(), vec![])?;
+    Ok(rows
+        .first()
+        .and_then(|row| row.get("seq"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0))
+}
+
+/// Look up a single live entry by its id
+pub fn get_entry(db: &Sqlite, id: &str) -> anyhow::Result<Option<LogEntry>> {
+    let statement = format!("SELECT {} FROM log_entries WHERE uuid = ? AND cleared_in IS NULL;", ENTRY_COLUMNS);
+    let rows = db.read(statement, vec![Value::String(id.to_string())])?;
+    Ok(rows.first().map(row_to_entry))
+}
+
+/// Which of `ids` are already stored, live or soft-deleted
+pub fn existing_ids(db: &Sqlite, ids: &[String]) -> anyhow::Result<HashSet<String>> {
+    Ok(existing_seqs(db, ids)?.into_keys().collect())
+}
+
+/// Sequence numbers of those `ids` that are already stored, live or soft-deleted
+pub fn existing_seqs(db: &Sqlite, ids: &[String]) -> anyhow::Result<HashMap<String, u64>> {
+    let mut existing = HashMap::new();
+    for chunk in ids.chunks(ID_LOOKUP_CHUNK) {
+        let placeholders = vec!["?"; chunk.len()].join(", ");
+        let statement = format!("SELECT uuid, seq FROM log_entries WHERE uuid IN ({});", placeholders);
+        let params = chunk.iter().cloned().map(Value::String).collect();
+        for row in db.read(statement, params)? {
+            let id = row.get("uuid").and_then(Value::as_str);
+            let seq = row.get("seq").and_then(Value::as_u64);
+            if let (Some(id), Some(seq)) = (id, seq) {
+                existing.insert(id.to_string(), seq);
+            }
+        }
+    }
+    Ok(existing)
+}
+
+/// Number of live entries currently stored
+pub fn count(db: &Sqlite) -> anyhow::Result<u64> {
+    let rows = db.read("SELECT COUNT(*) AS count FROM log_entries WHERE cleared_in IS NULL;".to_string(), vec![])?;
+    Ok(rows
+        .first()
+        .and_then(|row| row.get("count"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0))
+}
+
+/// One page of live entries matching `query`, plus the cursor for the next page.
+/// Pages run newest first, except `since_seq` queries which run oldest first.
+pub fn query_entries(db: &Sqlite, query: &HistoryQuery) -> anyhow::Result<(Vec<LogEntry>, Option<u64>)> {
+    let (statement, params) = history_statement(query);
+    let rows = db.read(statement, params)?;
+    let entries: Vec<LogEntry> = rows.iter().map(row_to_entry).collect();
+    Ok(history::into_page(entries, page_size(query) as usize))
+}
+
+// Helper function to build the SELECT for `query_entries`: the query's filters and cursors,
+// ordered for the page direction, fetching one row past the page size
+fn history_statement(query: &HistoryQuery) -> (String, Vec<Value>) {
+    let mut clauses: Vec<&str> = vec!["cleared_in IS NULL"];
+    let mut params: Vec<Value> = Vec::new();
+
+    if let Some(channel) = &query.channel {
+        clauses.push("channel = ?");
+        params.push(Value::String(channel_key(channel)));
+    }
+    if let Some(type_name) = &query.type_name {
+        clauses.push("type_name = ?");
+        params.push(Value::String(type_key(type_name)));
+    }
+    if let Some(prefix) = &query.source_prefix {
+        clauses.push("source LIKE ? ESCAPE '\\'");
+        params.push(Value::String(format!("{}%", escape_like(prefix))));
+    }
+    if let Some(since) = query.since {
+        clauses.push("timestamp >= ?");
+        params.push(Value::from(since));
+    }
+    if let Some(until) = query.until {
+        clauses.push("timestamp <= ?");
+        params.push(Value::from(until));
+    }
+    if let Some(min_severity) = query.min_severity {
+        clauses.push("severity >= ?");
+        params.push(Value::from(severity_level(min_severity)));
+    }
+    if let Some(needle) = &query.content_contains {
+        clauses.push("instr(content, ?) > 0");
+        params.push(Value::String(needle.clone()));
+    }
+    if let Some(cursor) = query.cursor {
+        clauses.push("seq < ?");
+        params.push(Value::from(cursor));
+    }
+    if let Some(since_seq) = query.since_seq {
+        clauses.push("seq > ?");
+        params.push(Value::from(since_seq));
+    }
+    let order = if query.since_seq.is_some() { "ASC" } else { "DESC" };
+
+    // Fetch one extra row to find out whether another page exists
+    params.push(Value::from(page_size(query) + 1));
+
+    let statement = format!(
+        "SELECT {} FROM log_entries WHERE {} ORDER BY seq {} LIMIT ?;",
+        ENTRY_COLUMNS,
+        clauses.join(" AND "),
+        order,
+    );
+    (statement, params)
+}
+
+/// One page of live entries matching the FTS5 expression `match_expression`, best first,
+/// plus the offset of the next page
+pub fn search_entries(
+    db: &Sqlite,
+    match_expression: &str,
+    query: &SearchQuery,
+) -> anyhow::Result<(Vec<SearchHit>, Option<u64>)> {
+    let mut clauses: Vec<&str> = vec!["log_search MATCH ?", "e.cleared_in IS NULL"];
+    let mut params: Vec<Value> = vec![Value::String(match_expression.to_string())];
+
+    if let Some(channel) = &query.channel {
+        clauses.push("e.channel = ?");
+        params.push(Value::String(channel_key(channel)));
+    }
+    if let Some(type_name) = &query.type_name {
+        clauses.push("e.type_name = ?");
+        params.push(Value::String(type_key(type_name)));
+    }
+    if let Some(prefix) = &query.source_prefix {
+        clauses.push("e.source LIKE ? ESCAPE '\\'");
+        params.push(Value::String(format!("{}%", escape_like(prefix))));
+    }
+    if let Some(since) = query.since {
+        clauses.push("e.timestamp >= ?");
+        params.push(Value::from(since));
+    }
+    if let Some(until) = query.until {
+        clauses.push("e.timestamp <= ?");
+        params.push(Value::from(until));
+    }
+    if let Some(min_severity) = query.min_severity {
+        clauses.push("e.severity >= ?");
+        params.push(Value::from(severity_level(min_severity)));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0);
+    // Fetch one extra row to find out whether another page exists
+    params.push(Value::from(limit + 1));
+    params.push(Value::from(offset));
+
+    // bm25 is lower for better matches; ties go to the newest entry
+    let columns: Vec<String> = ENTRY_COLUMNS.split(", ").map(|column| format!("e.{}", column)).collect();
+    let statement = format!(
+        "SELECT {}, -bm25(log_search) AS score, \
+        snippet(log_search, -1, '<mark>', '</mark>', '…', {}) AS snippet \
+        FROM log_search JOIN log_entries e ON e.id = log_search.rowid \
+        WHERE {} ORDER BY bm25(log_search), e.seq DESC LIMIT ? OFFSET ?;",
+        columns.join(", "),
+        SNIPPET_TOKENS,
+        clauses.join(" AND "),
+    );
+
+    let rows = db.read(statement, params)?;
+    let has_more = rows.len() as u64 > limit;
+    let hits = rows
+        .iter()
+        .take(limit as usize)
+        .map(|row| SearchHit {
+            entry: row_to_entry(row),
+            score: row.get("score").and_then(Value::as_f64).unwrap_or(0.0),
+            snippet: row.get("snippet").and_then(Value::as_str).unwrap_or_default().to_string(),
+        })
+        .collect::<Vec<_>>();
+    let next_offset = has_more.then(|| offset + hits.len() as u64);
+
+    Ok((hits, next_offset))
+}
+
+/// Number of entries a page of `query` holds at most
+pub fn page_size(query: &HistoryQuery) -> u64 {
+    query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE)
+}
+
+/// Storage key for a channel: its variant name
+pub fn channel_key(channel: &MessageChannel) -> String {
+    format!("{:?}", channel)
+}
+
+/// Storage key for a message type: its variant name, or `Other:<name>` for custom types
+pub fn type_key(message_type: &MessageType) -> String {
+    match message_type {
+        MessageType::Other(name) => format!("Other:{}", name),
+        unit => format!("{:?}", unit),
+    }
+}
+
+// Inverse of `channel_key`; unknown keys land on Internal rather than dropping the row
+fn parse_channel(key: &str) -> MessageChannel {
+    serde_json::from_value(Value::String(key.to_string())).unwrap_or(MessageChannel::Internal)
+}
+
+// Inverse of `type_key`; anything unrecognised is kept verbatim as a custom type
+fn parse_type(key: &str) -> MessageType {
+    if let Some(name) = key.strip_prefix("Other:") {
+        return MessageType::Other(name.to_string());
+    }
+    serde_json::from_value(Value::String(key.to_string()))
+        .unwrap_or_else(|_| MessageType::Other(key.to_string()))
+}
+
+/// Storage value for a severity; ordered so `>=` means "at least this severe"
+pub fn severity_level(severity: Severity) -> u64 {
+    match severity {
+        Severity::Trace => 0,
+        Severity::Debug => 1,
+        Severity::Info => 2,
+        Severity::Warn => 3,
+        Severity::Error => 4,
+    }
+}
+
+// Inverse of `severity_level`
+fn parse_severity(level: u64) -> Severity {
+    match level {
+        0 => Severity::Trace,
+        1 => Severity::Debug,
+        3 => Severity::Warn,
+        4 => Severity::Error,
+        _ => Severity::Info,
+    }
+}
+
+// Escape LIKE wildcards so user input only ever matches literally
+fn escape_like(input: &str) -> String {
+    input
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// Convert a row returned by the sqlite service into a LogEntry
+fn row_to_entry(row: &HashMap<String, Value>) -> LogEntry {
+    let text = |column: &str| {
+        row.get(column)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    LogEntry {
+        id: text("uuid"),
+        seq: row.get("seq").and_then(Value::as_u64).unwrap_or(0),
+        source: text("source"),
+        channel: parse_channel(&text("channel")),
+        type_name: parse_type(&text("type_name")),
+        content: row.get("content").and_then(Value::as_str).map(str::to_string),
+        timestamp: row.get("timestamp").and_then(Value::as_u64).unwrap_or(0),
+        severity: parse_severity(row.get("severity").and_then(Value::as_u64).unwrap_or(2)),
+        attributes: serde_json::from_str(&text("attributes")).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_history_query_pages_newest_first() {
+        let (statement, params) = history_statement(&HistoryQuery::default());
+        assert_eq!(
+            statement,
+            format!("SELECT {} FROM log_entries WHERE cleared_in IS NULL ORDER BY seq DESC LIMIT ?;", ENTRY_COLUMNS),
+        );
+        assert_eq!(params, vec![Value::from(DEFAULT_PAGE_SIZE + 1)]);
+    }
+
+    #[test]
+    fn since_seq_pages_oldest_first_and_keeps_the_filters() {
+        let query = HistoryQuery {
+            channel: Some(MessageChannel::External),
+            min_severity: Some(Severity::Warn),
+            since_seq: Some(41),
+            limit: Some(10),
+            ..HistoryQuery::default()
+        };
+        let (statement, params) = history_statement(&query);
+        assert!(statement.ends_with(
+            "WHERE cleared_in IS NULL AND channel = ? AND severity >= ? AND seq > ? ORDER BY seq ASC LIMIT ?;"
+        ));
+        assert_eq!(
+            params,
+            vec![Value::from("External"), Value::from(3u64), Value::from(41u64), Value::from(11u64)],
+        );
+    }
+
+    #[test]
+    fn cursor_pages_walk_back_and_the_limit_is_clamped() {
+        let query = HistoryQuery {
+            cursor: Some(100),
+            limit: Some(MAX_PAGE_SIZE * 10),
+            ..HistoryQuery::default()
+        };
+        let (statement, params) = history_statement(&query);
+        assert!(statement.ends_with("WHERE cleared_in IS NULL AND seq < ? ORDER BY seq DESC LIMIT ?;"));
+        assert_eq!(params, vec![Value::from(100u64), Value::from(MAX_PAGE_SIZE + 1)]);
+
+        let (_, params) = history_statement(&HistoryQuery { limit: Some(0), ..HistoryQuery::default() });
+        assert_eq!(params, vec![Value::from(2u64)]);
+    }
+
+    #[test]
+    fn source_prefixes_match_literally() {
+        let query = HistoryQuery {
+            source_prefix: Some("100%_done\\".to_string()),
+            ..HistoryQuery::default()
+        };
+        let (statement, params) = history_statement(&query);
+        assert!(statement.contains("source LIKE ? ESCAPE '\\'"));
+        assert_eq!(params[0], Value::from("100\\%\\_done\\\\%"));
+    }
+
+    #[test]
+    fn type_keys_round_trip() {
+        for message_type in [MessageType::HttpGet, MessageType::Other("Custom:Thing".to_string())] {
+            assert_eq!(parse_type(&type_key(&message_type)), message_type);
+        }
+        assert_eq!(parse_channel(&channel_key(&MessageChannel::Timer)), MessageChannel::Timer);
+    }
+
+    #[test]
+    fn batches_are_counted_per_channel_in_first_seen_order() {
+        let entry = |seq: u64, channel| LogEntry {
+            id: format!("entry-{}", seq),
+            seq,
+            source: "test".to_string(),
+            channel,
+            type_name: MessageType::TimerTick,
+            content: None,
+            timestamp: 1,
+            severity: Severity::Info,
+            attributes: Vec::new(),
+        };
+        let entries = vec![
+            entry(1, MessageChannel::Timer),
+            entry(2, MessageChannel::External),
+            entry(3, MessageChannel::Timer),
+            entry(4, MessageChannel::Timer),
+        ];
+        assert_eq!(
+            count_channels(&entries),
+            vec![(MessageChannel::Timer, 3), (MessageChannel::External, 1)],
+        );
+        assert!(count_channels(&[]).is_empty());
+    }
+}
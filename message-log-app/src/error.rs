@@ -0,0 +1,68 @@
+use std::fmt;
+use hyperware_app_common::set_response_status;
+use hyperware_process_lib::http::server::StatusCode;
+use crate::types::{AppError, ErrorResponse};
+
+impl AppError {
+    /// Stable machine-readable code; clients may match on these
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::NotFound(_) => "not_found",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Unavailable(_) => "unavailable",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    /// Human-readable detail
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::BadRequest(message)
+            | AppError::NotFound(message)
+            | AppError::Forbidden(message)
+            | AppError::Unavailable(message)
+            | AppError::Internal(message) => message,
+        }
+    }
+
+    /// HTTP status used when the error is returned over HTTP
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Give the reply the hyperprocess wrapper sends for the current HTTP request this
+    /// error's status; its body stays the serialized `{"Err": ...}`. Outside an HTTP
+    /// request there is no reply status and this does nothing.
+    pub fn set_http_status(&self) {
+        set_response_status(self.status_code());
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(error: anyhow::Error) -> Self {
+        AppError::Internal(error.to_string())
+    }
+}
+
+impl From<&AppError> for ErrorResponse {
+    fn from(error: &AppError) -> Self {
+        ErrorResponse {
+            success: false,
+            code: error.code().to_string(),
+            message: error.message().to_string(),
+        }
+    }
+}
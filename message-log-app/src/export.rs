@@ -0,0 +1,222 @@
+use hyperware_app_common::get_server;
+use hyperware_process_lib::{
+    sqlite::Sqlite,
+    vfs::{create_file, open_file},
+};
+use crate::archive;
+use crate::db;
+use crate::types::{AppError, DownloadResponse, ExportFormat, HistoryQuery, LogEntry};
+
+/// Directory on the app's drive holding exports
+const EXPORTS_DIR: &str = "exports";
+
+/// Exports are served as static files under this path, one binding per file
+const DOWNLOADS_PATH: &str = "/api/download";
+
+/// Column order of CSV exports
+const CSV_HEADER: &str = "id,seq,timestamp,severity,channel,type_name,source,content,attributes\n";
+
+impl ExportFormat {
+    /// File extension for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Csv => "csv",
+        }
+    }
+
+    /// MIME type reported with downloads of this format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv",
+        }
+    }
+
+    // Format of an export file, from its extension
+    fn from_file_name(name: &str) -> Option<Self> {
+        match name.rsplit_once('.')?.1 {
+            "jsonl" => Some(ExportFormat::Jsonl),
+            "csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+
+    // Serialize one page of entries in this format
+    fn encode(&self, entries: &[LogEntry]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            ExportFormat::Jsonl => archive::to_jsonl(entries),
+            ExportFormat::Csv => {
+                let mut bytes = Vec::new();
+                for entry in entries {
+                    bytes.extend_from_slice(csv_row(entry)?.as_bytes());
+                }
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+/// Write every entry matching the query's filters, oldest first, to a new export file.
+/// `limit` and `cursor` are ignored; `since_seq` still sets the starting point.
+/// Returns the file's path, its name and how many entries it holds.
+pub fn export_entries(
+    db: &Sqlite,
+    query: &HistoryQuery,
+    format: ExportFormat,
+    export_id: u64,
+) -> anyhow::Result<(String, String, u64)> {
+    let file_name = format!("export-{}.{}", export_id, format.extension());
+    let path = format!("{}/{}", archive::dir_path(EXPORTS_DIR)?, file_name);
+    let mut file = create_file(&path, None)?;
+    if format == ExportFormat::Csv {
+        file.append(CSV_HEADER.as_bytes())?;
+    }
+
+    let mut page_query = HistoryQuery {
+        limit: Some(u64::MAX),
+        cursor: None,
+        since_seq: Some(query.since_seq.unwrap_or(0)),
+        ..query.clone()
+    };
+    let mut exported = 0;
+    loop {
+        let (entries, next_cursor) = db::query_entries(db, &page_query)?;
+        file.append(&format.encode(&entries)?)?;
+        exported += entries.len() as u64;
+        match next_cursor {
+            Some(seq) => page_query.since_seq = Some(seq),
+            None => break,
+        }
+    }
+    file.sync_all()?;
+
+    Ok((path, file_name, exported))
+}
+
+/// Publish an export file's raw bytes on its own static binding, served with the format's
+/// MIME type. The URL ends in the file name, which browsers save it under. Exports hold the
+/// whole log, so the binding requires a login even while `http_auth` is off.
+pub fn publish_download(file_name: &str) -> Result<DownloadResponse, AppError> {
+    // Only bare names of files we wrote are accepted, never paths
+    let format = ExportFormat::from_file_name(file_name)
+        .filter(|_| file_name.starts_with("export-") && !file_name.contains(['/', '\\']) && !file_name.contains(".."))
+        .ok_or_else(|| AppError::BadRequest(format!("Not an export file: {}", file_name)))?;
+
+    let path = format!("{}/{}", archive::dir_path(EXPORTS_DIR)?, file_name);
+    let file = open_file(&path, false, None)
+        .map_err(|_| AppError::NotFound(format!("No export named {}", file_name)))?;
+    let bytes = file
+        .read()
+        .map_err(|e| AppError::Internal(format!("Failed to read {}: {:?}", file_name, e)))?;
+    let size = bytes.len() as u64;
+
+    let url_path = format!("{}/{}", DOWNLOADS_PATH, file_name);
+    let server = get_server().ok_or_else(|| AppError::Unavailable("HTTP server not ready".to_string()))?;
+    server
+        .bind_http_static_path(&url_path, true, false, Some(format.content_type().to_string()), bytes)
+        .map_err(|e| AppError::Internal(format!("Failed to publish {}: {:?}", file_name, e)))?;
+
+    Ok(DownloadResponse {
+        file_name: file_name.to_string(),
+        content_type: format.content_type().to_string(),
+        url_path,
+        size,
+    })
+}
+
+// Render one entry as a CSV line, attributes as a JSON array
+fn csv_row(entry: &LogEntry) -> anyhow::Result<String> {
+    let fields = [
+        entry.id.clone(),
+        entry.seq.to_string(),
+        entry.timestamp.to_string(),
+        format!("{:?}", entry.severity),
+        db::channel_key(&entry.channel),
+        db::type_key(&entry.type_name),
+        entry.source.clone(),
+        entry.content.clone().unwrap_or_default(),
+        serde_json::to_string(&entry.attributes)?,
+    ];
+    let escaped: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    Ok(format!("{}\n", escaped.join(",")))
+}
+
+// Quote a CSV field if it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MessageChannel, MessageType, Severity};
+
+    #[test]
+    fn plain_fields_are_left_alone() {
+        assert_eq!(csv_field("hello world"), "hello world");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn separators_quotes_and_line_breaks_are_quoted() {
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\rhere"), "\"cr\rhere\"");
+    }
+
+    #[test]
+    fn rows_follow_the_header() {
+        let entry = LogEntry {
+            id: "8f0c6f3e-54a1-4b43-9d4e-6f8f0b6f1f11".to_string(),
+            seq: 7,
+            source: "HTTP:Custom".to_string(),
+            channel: MessageChannel::HttpApi,
+            type_name: MessageType::Other("Deploy".to_string()),
+            content: Some("done, \"finally\"".to_string()),
+            timestamp: 1_700_000_000,
+            severity: Severity::Warn,
+            attributes: vec![("env".to_string(), "prod".to_string())],
+        };
+        let row = csv_row(&entry).unwrap();
+        assert_eq!(
+            row,
+            format!(
+                "{},7,1700000000,Warn,HttpApi,Other:Deploy,HTTP:Custom,\"done, \"\"finally\"\"\",\"[[\"\"env\"\",\"\"prod\"\"]]\"\n",
+                entry.id,
+            ),
+        );
+        assert_eq!(CSV_HEADER.matches(',').count(), 8);
+    }
+
+    #[test]
+    fn missing_content_is_an_empty_field() {
+        let entry = LogEntry {
+            id: "id".to_string(),
+            seq: 1,
+            source: "s".to_string(),
+            channel: MessageChannel::Internal,
+            type_name: MessageType::TimerTick,
+            content: None,
+            timestamp: 0,
+            severity: Severity::Info,
+            attributes: Vec::new(),
+        };
+        assert!(csv_row(&entry).unwrap().ends_with(",s,,[]\n"));
+    }
+
+    #[test]
+    fn export_formats_round_trip_through_file_names() {
+        for format in [ExportFormat::Jsonl, ExportFormat::Csv] {
+            let name = format!("export-1.{}", format.extension());
+            assert_eq!(ExportFormat::from_file_name(&name), Some(format));
+        }
+        assert!(ExportFormat::from_file_name("export-1.txt").is_none());
+        assert!(ExportFormat::from_file_name("export").is_none());
+    }
+}
@@ -0,0 +1,398 @@
+use hyperware_app_common::{send, SendResult};
+use hyperware_process_lib::{logging::error, our, Address, Request};
+use serde_json::json;
+use crate::access::ACCESS_CONTROL_SOURCE;
+use crate::audit::AUDIT_SOURCE_PREFIX;
+use crate::db;
+use crate::types::{
+    AppError, BatchResponse, ExternalLogMessage, ForwardingPolicy, LogEntry, MessageChannel, MessageType, Severity,
+};
+use crate::timers::TimerKind;
+use crate::{log_structured, AppState, MAX_ATTRIBUTES, MAX_BATCH_SIZE};
+
+/// Source of the entries forwarding logs about itself; these are never forwarded
+const FORWARDING_SOURCE: &str = "Forwarding";
+
+/// Attribute naming the node a forwarded entry came from. Entries carrying it are
+/// not forwarded again.
+const ORIGIN_NODE: &str = "origin_node";
+
+/// Attributes `forwarded_message` adds on top of the entry's own
+const ORIGIN_ATTRIBUTES: usize = 6;
+
+/// Shortest flush interval we will arm a timer for
+const MIN_FLUSH_INTERVAL_MS: u64 = 1_000;
+
+/// Longest wait between flushes while the target keeps failing
+const MAX_FORWARD_BACKOFF_MS: u64 = 300_000;
+
+/// Seconds to wait for the target to acknowledge a batch
+const FORWARD_TIMEOUT_SECS: u64 = 30;
+
+/// How a flush ended
+enum FlushOutcome {
+    Delivered,
+    /// The target answered but will never accept these entries
+    Rejected(String),
+    /// The target could not be reached or failed; the batch stays in the queue
+    Failed(&'static str, String),
+}
+
+impl ForwardingPolicy {
+    /// Whether a newly stored entry should be sent to the target. Entries that arrived
+    /// by forwarding, and the ones the app logs about requests it handled (audit and
+    /// access control) or about forwarding itself, stay local: receiving a batch logs
+    /// an audit entry, and forwarding that back would bounce between two nodes forever.
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        self.enabled
+            && (self.channels.is_empty() || self.channels.contains(&entry.channel))
+            && entry.severity >= self.min_severity
+            && entry.source != FORWARDING_SOURCE
+            && entry.source != ACCESS_CONTROL_SOURCE
+            && !entry.source.starts_with(AUDIT_SOURCE_PREFIX)
+            && !entry.attributes.iter().any(|(key, _)| key == ORIGIN_NODE)
+    }
+
+    /// Reject limits that are out of range and, when enabled, a missing or unusable target
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.batch_size == 0 || self.batch_size > MAX_BATCH_SIZE as u64 {
+            return Err(AppError::BadRequest(format!("forwarding.batch_size must be between 1 and {}", MAX_BATCH_SIZE)));
+        }
+        if self.flush_interval_ms < MIN_FLUSH_INTERVAL_MS {
+            return Err(AppError::BadRequest(format!("forwarding.flush_interval_ms must be at least {}", MIN_FLUSH_INTERVAL_MS)));
+        }
+        if self.max_queue < self.batch_size {
+            return Err(AppError::BadRequest("forwarding.max_queue must be at least forwarding.batch_size".to_string()));
+        }
+        if self.enabled {
+            let target = self.target_address()?;
+            if target == our() {
+                return Err(AppError::BadRequest("forwarding.target must not be this app".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    fn target_address(&self) -> Result<Address, AppError> {
+        let target = self
+            .target
+            .as_deref()
+            .ok_or_else(|| AppError::BadRequest("forwarding.target is required when forwarding is enabled".to_string()))?;
+        target
+            .parse::<Address>()
+            .map_err(|_| AppError::BadRequest(format!("Invalid forwarding target address: {}", target)))
+    }
+}
+
+// Helper function to turn a stored entry into a batch message for the target,
+// keeping where it came from (`origin_node` is our node) in `origin_*` attributes
+fn forwarded_message(entry: &LogEntry, origin_node: &str) -> ExternalLogMessage {
+    let mut attributes: Vec<(String, String)> = entry
+        .attributes
+        .iter()
+        .take(MAX_ATTRIBUTES - ORIGIN_ATTRIBUTES)
+        .cloned()
+        .collect();
+    attributes.extend([
+        (ORIGIN_NODE.to_string(), origin_node.to_string()),
+        ("origin_id".to_string(), entry.id.clone()),
+        ("origin_seq".to_string(), entry.seq.to_string()),
+        ("origin_source".to_string(), entry.source.clone()),
+        ("origin_channel".to_string(), db::channel_key(&entry.channel)),
+        ("origin_timestamp".to_string(), entry.timestamp.to_string()),
+    ]);
+
+    ExternalLogMessage {
+        // Reusing the entry's id lets the target ignore a batch it already stored
+        id: Some(entry.id.clone()),
+        message_type: match &entry.type_name {
+            MessageType::Other(name) => name.clone(),
+            unit => db::type_key(unit),
+        },
+        content: entry.content.clone().unwrap_or_default(),
+        severity: Some(entry.severity),
+        attributes,
+    }
+}
+
+impl AppState {
+    /// Queue the newly stored entries the forwarding policy selects. The queue lives in
+    /// the database, so entries waiting for the target survive a restart.
+    pub(crate) fn queue_for_forwarding(&mut self, entries: &[LogEntry]) {
+        let messages: Vec<ExternalLogMessage> = entries
+            .iter()
+            .filter(|entry| self.config.forwarding.matches(entry))
+            .map(|entry| forwarded_message(entry, our().node()))
+            .collect();
+        if messages.is_empty() {
+            return;
+        }
+        let Some(db) = &self.db else {
+            return;
+        };
+        if let Err(e) = db::enqueue_forward(db, &messages) {
+            error!("Failed to queue {} entries for forwarding: {:?}", messages.len(), e);
+            self.metrics.record_forward_dropped("queue_error", messages.len() as u64);
+            return;
+        }
+        self.forward_queued += messages.len() as u64;
+        self.trim_forward_queue();
+    }
+
+    /// Arm the next flush, unless forwarding is off. A full batch waiting goes out
+    /// right away; after failures the wait doubles up to `MAX_FORWARD_BACKOFF_MS`.
+    /// Any timer armed earlier goes stale and is ignored by `handle_timer`.
+    pub(crate) fn schedule_forward_timer(&mut self) {
+        let policy = &self.config.forwarding;
+        if !policy.enabled {
+            self.timers.disarm(TimerKind::Forward);
+            return;
+        }
+
+        let delay = if self.forward_failures > 0 {
+            policy
+                .flush_interval_ms
+                .saturating_mul(1u64 << self.forward_failures.min(16))
+                .min(MAX_FORWARD_BACKOFF_MS.max(policy.flush_interval_ms))
+        } else if !self.forward_in_flight && self.forward_queued >= policy.batch_size {
+            0
+        } else {
+            policy.flush_interval_ms
+        };
+        self.timers.arm(TimerKind::Forward, delay);
+    }
+
+    /// Start a flush if entries are waiting and none is running, otherwise just re-arm.
+    /// The send is awaited by the `flush_forwarding` handler, which we reach by messaging ourselves.
+    pub(crate) fn start_forward_flush(&mut self) {
+        if self.forward_in_flight || self.forward_queued == 0 {
+            self.schedule_forward_timer();
+            return;
+        }
+        let body = json!({"FlushForwarding": {}});
+        if let Err(e) = Request::to(our()).body(body.to_string().into_bytes()).send() {
+            error!("Failed to start forwarding flush: {}", e);
+            self.schedule_forward_timer();
+            return;
+        }
+
+        // The flush re-arms the timer when it finishes; this tick only matters if it never runs
+        let delay = FORWARD_TIMEOUT_SECS * 1_000 + self.config.forwarding.flush_interval_ms;
+        self.timers.arm(TimerKind::Forward, delay);
+    }
+
+    /// Drop the queue when forwarding is turned off, then re-arm the flush timer
+    pub(crate) fn apply_forwarding_policy(&mut self) {
+        self.forward_failures = 0;
+        if !self.config.forwarding.enabled && self.forward_queued > 0 {
+            match self.db().and_then(|db| Ok(db::clear_forward_queue(db)?)) {
+                Ok(()) => {
+                    self.metrics.record_forward_dropped("disabled", self.forward_queued);
+                    self.forward_queued = 0;
+                }
+                Err(e) => error!("Failed to clear the forwarding queue: {}", e),
+            }
+        }
+        self.schedule_forward_timer();
+    }
+
+    /// Send the oldest queued entries to the target as one batch and return how many it accepted.
+    /// The batch stays in the queue until the target answers, so a failed send or a restart
+    /// while it is out loses nothing; new entries can still be queued behind it meanwhile.
+    pub(crate) async fn flush_forward_queue(&mut self) -> Result<u64, AppError> {
+        if self.forward_in_flight || self.forward_queued == 0 {
+            return Ok(0);
+        }
+        let target = self.config.forwarding.target_address()?;
+        let queued = db::forward_batch(self.db()?, self.config.forwarding.batch_size)?;
+        let Some(last_id) = queued.last().map(|(id, _)| *id) else {
+            self.forward_queued = 0;
+            return Ok(0);
+        };
+        let batch: Vec<ExternalLogMessage> = queued.into_iter().map(|(_, message)| message).collect();
+        let count = batch.len();
+
+        self.forward_in_flight = true;
+        let request = json!({"LogExternalBatch": &batch});
+        let result = send::<Result<BatchResponse, AppError>>(&request, &target, FORWARD_TIMEOUT_SECS).await;
+        self.forward_in_flight = false;
+
+        let outcome = match result {
+            SendResult::Success(Ok(response)) if response.success => FlushOutcome::Delivered,
+            SendResult::Success(Ok(response)) => FlushOutcome::Rejected(response.message),
+            SendResult::Success(Err(AppError::BadRequest(message))) => FlushOutcome::Rejected(message),
+            SendResult::Success(Err(e)) => FlushOutcome::Failed(e.code(), e.to_string()),
+            SendResult::Timeout => FlushOutcome::Failed("timeout", "timed out".to_string()),
+            SendResult::Offline => FlushOutcome::Failed("offline", "target is offline".to_string()),
+            SendResult::DeserializationError(e) => FlushOutcome::Failed("bad_response", e),
+        };
+
+        let result = match outcome {
+            FlushOutcome::Delivered => {
+                self.remove_forwarded(last_id);
+                self.metrics.record_forwarded(count as u64);
+                if self.forward_failures > 0 {
+                    self.log_forwarding(
+                        "ForwardingResumed",
+                        Severity::Info,
+                        format!("Forwarding to {} resumed; {} entries still queued", target, self.forward_queued),
+                    );
+                }
+                self.forward_failures = 0;
+                Ok(count as u64)
+            }
+            FlushOutcome::Rejected(message) => {
+                // Retrying entries the target refuses would hold up the rest of the queue
+                self.remove_forwarded(last_id);
+                self.metrics.record_forward_dropped("rejected", count as u64);
+                self.log_forwarding(
+                    "ForwardingRejected",
+                    Severity::Warn,
+                    format!("{} refused a batch of {} entries, dropped: {}", target, count, message),
+                );
+                self.forward_failures = 0;
+                Ok(0)
+            }
+            FlushOutcome::Failed(reason, message) => {
+                // The batch never left the queue, so the next flush sends it again
+                self.metrics.record_forward_failure(reason);
+                self.forward_failures += 1;
+                // Only the first failure of a streak is logged; the rest show up in /metrics
+                if self.forward_failures == 1 {
+                    self.log_forwarding(
+                        "ForwardingFailed",
+                        Severity::Warn,
+                        format!(
+                            "Forwarding to {} failed ({}); {} entries queued, retrying with backoff",
+                            target, message, self.forward_queued,
+                        ),
+                    );
+                }
+                Err(AppError::Unavailable(format!("Forwarding to {} failed: {}", target, message)))
+            }
+        };
+
+        self.schedule_forward_timer();
+        result
+    }
+
+    // Take a batch the target has answered for out of the queue. If this fails the batch
+    // is sent again later; the target recognises the ids and does not store it twice.
+    fn remove_forwarded(&mut self, last_id: u64) {
+        let Some(db) = &self.db else {
+            return;
+        };
+        match db::dequeue_forward(db, last_id).and_then(|()| db::forward_queue_len(db)) {
+            Ok(queued) => self.forward_queued = queued,
+            Err(e) => error!("Failed to remove forwarded entries from the queue: {:?}", e),
+        }
+    }
+
+    // Drop the oldest queued entries past `max_queue`
+    fn trim_forward_queue(&mut self) {
+        let max_queue = self.config.forwarding.max_queue;
+        if self.forward_queued <= max_queue {
+            return;
+        }
+        let Some(db) = &self.db else {
+            return;
+        };
+        if let Err(e) = db::trim_forward_queue(db, max_queue) {
+            error!("Failed to trim the forwarding queue: {:?}", e);
+            return;
+        }
+        self.metrics.record_forward_dropped("overflow", self.forward_queued - max_queue);
+        self.forward_queued = max_queue;
+    }
+
+    // Log a forwarding event locally; these entries are never forwarded themselves
+    fn log_forwarding(&mut self, event: &str, severity: Severity, content: String) {
+        let logged = log_structured(
+            self,
+            FORWARDING_SOURCE.to_string(),
+            MessageChannel::Internal,
+            MessageType::Other(event.to_string()),
+            Some(content),
+            severity,
+            Vec::new(),
+        );
+        if let Err(e) = logged {
+            error!("Failed to log {}: {}", event, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ForwardingPolicy {
+        ForwardingPolicy {
+            enabled: true,
+            target: Some("other.os@message-log-app:message-log-app:template.os".to_string()),
+            ..ForwardingPolicy::default()
+        }
+    }
+
+    fn entry(source: &str, attributes: Vec<(String, String)>) -> LogEntry {
+        LogEntry {
+            id: "6f1c2a9e-3b4d-4e5f-8a7b-9c0d1e2f3a4b".to_string(),
+            seq: 1,
+            source: source.to_string(),
+            channel: MessageChannel::External,
+            type_name: MessageType::Other("Test".to_string()),
+            content: Some("hello".to_string()),
+            timestamp: 1_700_000_000,
+            severity: Severity::Info,
+            attributes,
+        }
+    }
+
+    // What a node stores when a batch from `sender` arrives: each forwarded entry as
+    // `log_external_batch` stores it, then the audit entry for the request itself
+    fn receive(sender: &str, batch: Vec<ExternalLogMessage>) -> Vec<LogEntry> {
+        let mut stored: Vec<LogEntry> = batch
+            .into_iter()
+            .map(|message| entry(&format!("External:{}", sender), message.attributes))
+            .collect();
+        let audit_source = format!("{}{}@message-log-app:message-log-app:template.os", AUDIT_SOURCE_PREFIX, sender);
+        stored.push(entry(&audit_source, vec![("method".to_string(), "log_external_batch".to_string())]));
+        stored
+    }
+
+    #[test]
+    fn two_nodes_forwarding_to_each_other_do_not_loop() {
+        let nodes = ["a.os", "b.os"];
+        let policy = policy();
+        let mut stored = vec![entry("app:process:template.os", Vec::new())];
+        let mut node = 0;
+
+        // Each round, the node that just stored entries forwards what matches to the other
+        for _ in 0..10 {
+            let batch: Vec<ExternalLogMessage> = stored
+                .iter()
+                .filter(|entry| policy.matches(entry))
+                .map(|entry| forwarded_message(entry, nodes[node]))
+                .collect();
+            if batch.is_empty() {
+                return;
+            }
+            stored = receive(nodes[node], batch);
+            node = 1 - node;
+        }
+        panic!("entries were still being forwarded after 10 rounds");
+    }
+
+    #[test]
+    fn bookkeeping_entries_are_not_forwarded() {
+        let policy = policy();
+        assert!(policy.matches(&entry("app:process:template.os", Vec::new())));
+        assert!(!policy.matches(&entry(FORWARDING_SOURCE, Vec::new())));
+        assert!(!policy.matches(&entry(ACCESS_CONTROL_SOURCE, Vec::new())));
+        assert!(!policy.matches(&entry("Request:a.os@app:process:template.os", Vec::new())));
+
+        let forwarded = forwarded_message(&entry("app:process:template.os", Vec::new()), "a.os");
+        assert!(forwarded.attributes.contains(&(ORIGIN_NODE.to_string(), "a.os".to_string())));
+        assert!(!policy.matches(&entry("External:a.os", forwarded.attributes)));
+    }
+}
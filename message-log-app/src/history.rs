@@ -0,0 +1,267 @@
+use crate::db;
+use crate::ring::RingBuffer;
+use crate::types::{AppError, HistoryQuery, HistoryResponse, LogEntry};
+
+/// A page of entries and the cursor for the next one, as every history source returns it
+pub type Page = (Vec<LogEntry>, Option<u64>);
+
+/// In-memory version of the filters and cursors `db::query_entries` applies in SQL
+pub fn matches(query: &HistoryQuery, entry: &LogEntry) -> bool {
+    query.channel.map_or(true, |channel| channel == entry.channel)
+        && query.type_name.as_ref().map_or(true, |t| *t == entry.type_name)
+        && query.source_prefix.as_ref().map_or(true, |p| entry.source.starts_with(p.as_str()))
+        && query.since.map_or(true, |since| entry.timestamp >= since)
+        && query.until.map_or(true, |until| entry.timestamp <= until)
+        && query.min_severity.map_or(true, |severity| entry.severity >= severity)
+        && query.content_contains.as_ref().map_or(true, |needle| {
+            entry.content.as_ref().is_some_and(|content| content.contains(needle.as_str()))
+        })
+        && query.cursor.map_or(true, |cursor| entry.seq < cursor)
+        && query.since_seq.map_or(true, |since_seq| entry.seq > since_seq)
+}
+
+/// Cut entries fetched with one extra into a page of `limit`; the extra one only
+/// tells us another page exists, whose cursor is the last seq on this one
+pub fn into_page(mut entries: Vec<LogEntry>, limit: usize) -> Page {
+    if entries.len() <= limit {
+        return (entries, None);
+    }
+    entries.truncate(limit);
+    let next_cursor = entries.last().map(|entry| entry.seq);
+    (entries, next_cursor)
+}
+
+/// Answer an unfiltered newest-first page from the recent entries, if they cover it.
+/// `live_count` is how many live entries the database holds in all.
+pub fn recent_page(recent: &RingBuffer<LogEntry>, live_count: u64, query: &HistoryQuery) -> Option<HistoryResponse> {
+    let unfiltered = query.channel.is_none()
+        && query.type_name.is_none()
+        && query.source_prefix.is_none()
+        && query.since.is_none()
+        && query.until.is_none()
+        && query.min_severity.is_none()
+        && query.content_contains.is_none()
+        && query.since_seq.is_none();
+    if !unfiltered || (recent.is_empty() && live_count > 0) {
+        return None;
+    }
+
+    let limit = db::page_size(query) as usize;
+    let entries: Vec<LogEntry> = recent
+        .iter()
+        .rev()
+        .filter(|entry| query.cursor.map_or(true, |cursor| entry.seq < cursor))
+        .take(limit + 1)
+        .cloned()
+        .collect();
+    let (entries, next_cursor) = into_page(entries, limit);
+    if next_cursor.is_some() {
+        return Some(HistoryResponse { entries, next_cursor });
+    }
+
+    // A short page is only complete if nothing older is left in the database
+    if recent.len() as u64 == live_count && !query.include_archived.unwrap_or(false) {
+        Some(HistoryResponse { entries, next_cursor: None })
+    } else {
+        None
+    }
+}
+
+/// One page across the live log and the archived segments. Archived entries are all
+/// older than live ones: newest-first pages read the live log first and then the
+/// segments, oldest-first (`since_seq`) pages the other way round.
+pub fn merged_page(
+    query: &HistoryQuery,
+    read_live: impl Fn(&HistoryQuery) -> Result<Page, AppError>,
+    read_archived: impl Fn(&HistoryQuery) -> Result<Page, AppError>,
+) -> Result<HistoryResponse, AppError> {
+    let ascending = query.since_seq.is_some();
+    let read = |archived: bool, query: &HistoryQuery| if archived { read_archived(query) } else { read_live(query) };
+
+    let page_size = db::page_size(query);
+    let (mut entries, next_cursor) = read(ascending, query)?;
+    if next_cursor.is_some() {
+        return Ok(HistoryResponse { entries, next_cursor });
+    }
+    let mut rest_query = HistoryQuery {
+        limit: Some(page_size - entries.len() as u64),
+        ..query.clone()
+    };
+    if let Some(last) = entries.last() {
+        if ascending {
+            rest_query.since_seq = Some(last.seq);
+        } else {
+            rest_query.cursor = Some(last.seq);
+        }
+    }
+
+    let (rest, rest_cursor) = read(!ascending, &rest_query)?;
+    if entries.len() as u64 == page_size {
+        // The first source filled the page; the second only says whether another follows
+        let next_cursor = if rest.is_empty() { None } else { entries.last().map(|e| e.seq) };
+        return Ok(HistoryResponse { entries, next_cursor });
+    }
+    entries.extend(rest);
+    Ok(HistoryResponse { entries, next_cursor: rest_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MessageChannel, MessageType, Severity};
+
+    fn entry(seq: u64, channel: MessageChannel) -> LogEntry {
+        LogEntry {
+            id: format!("entry-{}", seq),
+            seq,
+            source: if seq % 2 == 0 { "even:app:template.os" } else { "odd:app:template.os" }.to_string(),
+            channel,
+            type_name: MessageType::Other("Test".to_string()),
+            content: Some(format!("message {}", seq)),
+            timestamp: 1_700_000_000 + seq,
+            severity: if seq % 3 == 0 { Severity::Warn } else { Severity::Info },
+            attributes: Vec::new(),
+        }
+    }
+
+    fn entries(seqs: impl IntoIterator<Item = u64>) -> Vec<LogEntry> {
+        seqs.into_iter().map(|seq| entry(seq, MessageChannel::Internal)).collect()
+    }
+
+    fn seqs(entries: &[LogEntry]) -> Vec<u64> {
+        entries.iter().map(|entry| entry.seq).collect()
+    }
+
+    // A history source the way `db::query_entries` and `segments::query_entries` behave:
+    // filtered, newest first unless resuming from `since_seq`, one page at a time
+    fn read(store: &[LogEntry], query: &HistoryQuery) -> Result<Page, AppError> {
+        let mut found: Vec<LogEntry> = store.iter().filter(|entry| matches(query, entry)).cloned().collect();
+        found.sort_by_key(|entry| entry.seq);
+        if query.since_seq.is_none() {
+            found.reverse();
+        }
+        let limit = db::page_size(query) as usize;
+        found.truncate(limit + 1);
+        Ok(into_page(found, limit))
+    }
+
+    fn query(limit: u64) -> HistoryQuery {
+        HistoryQuery {
+            limit: Some(limit),
+            include_archived: Some(true),
+            ..HistoryQuery::default()
+        }
+    }
+
+    #[test]
+    fn into_page_sets_a_cursor_only_when_more_follow() {
+        let (page, next_cursor) = into_page(entries([5, 4, 3]), 3);
+        assert_eq!((seqs(&page), next_cursor), (vec![5, 4, 3], None));
+
+        let (page, next_cursor) = into_page(entries([5, 4, 3, 2]), 3);
+        assert_eq!((seqs(&page), next_cursor), (vec![5, 4, 3], Some(3)));
+
+        assert_eq!(into_page(Vec::new(), 3).1, None);
+    }
+
+    #[test]
+    fn cursor_and_since_seq_are_exclusive() {
+        let entry = entry(10, MessageChannel::Internal);
+        let at = |cursor, since_seq| HistoryQuery { cursor, since_seq, ..HistoryQuery::default() };
+        assert!(!matches(&at(Some(10), None), &entry));
+        assert!(matches(&at(Some(11), None), &entry));
+        assert!(!matches(&at(None, Some(10)), &entry));
+        assert!(matches(&at(None, Some(9)), &entry));
+    }
+
+    #[test]
+    fn filters_combine_with_since_seq() {
+        let query = HistoryQuery {
+            channel: Some(MessageChannel::Internal),
+            source_prefix: Some("even:".to_string()),
+            min_severity: Some(Severity::Warn),
+            since_seq: Some(6),
+            ..HistoryQuery::default()
+        };
+        let found: Vec<u64> = entries(0..30).into_iter().filter(|e| matches(&query, e)).map(|e| e.seq).collect();
+        assert_eq!(found, vec![12, 18, 24]);
+
+        let other_channel = entry(12, MessageChannel::External);
+        assert!(!matches(&query, &other_channel));
+    }
+
+    #[test]
+    fn recent_page_walks_back_by_cursor() {
+        let mut recent = RingBuffer::with_capacity(10);
+        for entry in entries(1..=10) {
+            recent.push(entry);
+        }
+
+        let first = recent_page(&recent, 10, &HistoryQuery { limit: Some(4), ..HistoryQuery::default() }).unwrap();
+        assert_eq!(seqs(&first.entries), vec![10, 9, 8, 7]);
+        assert_eq!(first.next_cursor, Some(7));
+
+        let last = recent_page(&recent, 10, &HistoryQuery { limit: Some(4), cursor: Some(3), ..HistoryQuery::default() }).unwrap();
+        assert_eq!(seqs(&last.entries), vec![2, 1]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn recent_page_defers_to_the_database_when_it_cannot_answer() {
+        let mut recent = RingBuffer::with_capacity(5);
+        for entry in entries(6..=10) {
+            recent.push(entry);
+        }
+        // Older entries exist only in the database
+        assert!(recent_page(&recent, 10, &HistoryQuery { limit: Some(4), cursor: Some(8), ..HistoryQuery::default() }).is_none());
+        // Filters and resumes always go to the database
+        assert!(recent_page(&recent, 5, &HistoryQuery { since_seq: Some(7), ..HistoryQuery::default() }).is_none());
+        assert!(recent_page(&recent, 5, &HistoryQuery { min_severity: Some(Severity::Warn), ..HistoryQuery::default() }).is_none());
+        // Everything is in memory
+        assert!(recent_page(&recent, 5, &HistoryQuery { limit: Some(10), ..HistoryQuery::default() }).is_some());
+    }
+
+    #[test]
+    fn newest_first_pages_span_the_live_log_and_the_segments() {
+        let live = entries(8..=10);
+        let archived = entries(1..=7);
+        let page = |query: &HistoryQuery| merged_page(query, |q| read(&live, q), |q| read(&archived, q)).unwrap();
+
+        let first = page(&query(5));
+        assert_eq!(seqs(&first.entries), vec![10, 9, 8, 7, 6]);
+        assert_eq!(first.next_cursor, Some(6));
+
+        let second = page(&HistoryQuery { cursor: first.next_cursor, ..query(5) });
+        assert_eq!(seqs(&second.entries), vec![5, 4, 3, 2, 1]);
+        assert_eq!(second.next_cursor, None);
+    }
+
+    #[test]
+    fn oldest_first_pages_span_the_segments_and_the_live_log() {
+        let live = entries(8..=10);
+        let archived = entries(1..=7);
+        let page = |query: &HistoryQuery| merged_page(query, |q| read(&live, q), |q| read(&archived, q)).unwrap();
+
+        let first = page(&HistoryQuery { since_seq: Some(3), ..query(5) });
+        assert_eq!(seqs(&first.entries), vec![4, 5, 6, 7, 8]);
+        assert_eq!(first.next_cursor, Some(8));
+
+        let second = page(&HistoryQuery { since_seq: first.next_cursor, ..query(5) });
+        assert_eq!(seqs(&second.entries), vec![9, 10]);
+        assert_eq!(second.next_cursor, None);
+    }
+
+    #[test]
+    fn a_page_filled_by_the_first_source_probes_the_second() {
+        let live = entries(6..=10);
+        let page = |archived: &[LogEntry]| merged_page(&query(5), |q| read(&live, q), |q| read(archived, q)).unwrap();
+
+        let more = page(&entries(1..=5));
+        assert_eq!(seqs(&more.entries), vec![10, 9, 8, 7, 6]);
+        assert_eq!(more.next_cursor, Some(6));
+
+        let done = page(&[]);
+        assert_eq!(seqs(&done.entries), vec![10, 9, 8, 7, 6]);
+        assert_eq!(done.next_cursor, None);
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+use hyperware_process_lib::{get_blob, vfs::open_file};
+use uuid::Uuid;
+use crate::types::{AppError, ImportSource, LogEntry, RejectedLine};
+use crate::validate_attributes;
+
+/// Most rejected lines reported back individually
+const MAX_REPORTED_REJECTIONS: usize = 100;
+
+/// Largest import accepted, in bytes
+const MAX_IMPORT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Most entries (non-blank lines) accepted in one import
+const MAX_IMPORT_ENTRIES: usize = 100_000;
+
+/// Entries of a JSON Lines file that passed validation, plus what was rejected
+pub struct ParsedImport {
+    pub entries: Vec<LogEntry>,
+    pub rejected: Vec<RejectedLine>,
+    pub rejected_count: u64,
+}
+
+/// Read the raw bytes to import, rejecting payloads over `MAX_IMPORT_BYTES` or `MAX_IMPORT_ENTRIES`
+pub fn read_source(source: &ImportSource) -> Result<Vec<u8>, AppError> {
+    let bytes = match source {
+        ImportSource::Vfs(path) => {
+            let file = open_file(path, false, None)
+                .map_err(|_| AppError::NotFound(format!("No file at {}", path)))?;
+            // Check the size before pulling the whole file into memory
+            let len = file
+                .metadata()
+                .map_err(|e| AppError::Internal(format!("Failed to stat {}: {:?}", path, e)))?
+                .len;
+            check_limits(len, 0, MAX_IMPORT_BYTES, MAX_IMPORT_ENTRIES)?;
+            file.read()
+                .map_err(|e| AppError::Internal(format!("Failed to read {}: {:?}", path, e)))?
+        }
+        ImportSource::Upload(text) => text.clone().into_bytes(),
+        ImportSource::Blob => get_blob()
+            .map(|blob| blob.bytes)
+            .ok_or_else(|| AppError::BadRequest("Request has no blob attached".to_string()))?,
+    };
+    check_limits(bytes.len() as u64, count_lines(&bytes), MAX_IMPORT_BYTES, MAX_IMPORT_ENTRIES)?;
+    Ok(bytes)
+}
+
+// Helper function to reject an import of `len` bytes and `lines` entries over the given limits
+fn check_limits(len: u64, lines: usize, max_bytes: u64, max_entries: usize) -> Result<(), AppError> {
+    if len > max_bytes {
+        return Err(AppError::BadRequest(format!(
+            "Import is {} bytes; at most {} are allowed",
+            len, max_bytes,
+        )));
+    }
+    if lines > max_entries {
+        return Err(AppError::BadRequest(format!(
+            "Import has {} entries; at most {} are allowed",
+            lines, max_entries,
+        )));
+    }
+    Ok(())
+}
+
+// Number of non-blank lines, i.e. entries `parse_jsonl` will look at
+fn count_lines(bytes: &[u8]) -> usize {
+    bytes
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+        .count()
+}
+
+/// Parse and validate JSON Lines; blank lines are skipped, bad lines are rejected one by one
+pub fn parse_jsonl(bytes: &[u8]) -> ParsedImport {
+    let mut parsed = ParsedImport {
+        entries: Vec::new(),
+        rejected: Vec::new(),
+        rejected_count: 0,
+    };
+
+    for (index, line) in bytes.split(|b| *b == b'\n').enumerate() {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let result = serde_json::from_slice::<LogEntry>(line)
+            .map_err(|e| format!("Invalid entry: {}", e))
+            .and_then(|entry| validate_entry(&entry).map(|()| entry));
+        match result {
+            Ok(entry) => parsed.entries.push(entry),
+            Err(error) => {
+                parsed.rejected_count += 1;
+                if parsed.rejected.len() < MAX_REPORTED_REJECTIONS {
+                    parsed.rejected.push(RejectedLine { line: index as u64 + 1, error });
+                }
+            }
+        }
+    }
+
+    parsed
+}
+
+/// Drop entries whose id is in `seen` (already stored) or came earlier in the file.
+/// Returns the rest, in file order, and how many were dropped.
+pub fn drop_duplicates(entries: Vec<LogEntry>, mut seen: HashSet<String>) -> (Vec<LogEntry>, u64) {
+    let total = entries.len() as u64;
+    let kept: Vec<LogEntry> = entries
+        .into_iter()
+        .filter(|entry| seen.insert(entry.id.clone()))
+        .collect();
+    let dropped = total - kept.len() as u64;
+    (kept, dropped)
+}
+
+// Check an imported entry the way we check entries we log ourselves
+fn validate_entry(entry: &LogEntry) -> Result<(), String> {
+    if Uuid::parse_str(&entry.id).is_err() {
+        return Err(format!("Invalid id: {}", entry.id));
+    }
+    if entry.source.is_empty() {
+        return Err("Source must not be empty".to_string());
+    }
+    if entry.timestamp == 0 {
+        return Err("Timestamp must be set".to_string());
+    }
+    validate_attributes(&entry.attributes).map_err(|e| e.message().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MessageChannel, MessageType, Severity};
+
+    const ID_A: &str = "6f1c2a9e-3b4d-4e5f-8a7b-9c0d1e2f3a4b";
+    const ID_B: &str = "0a1b2c3d-4e5f-4a6b-8c7d-8e9f0a1b2c3d";
+
+    fn entry(id: &str, source: &str) -> LogEntry {
+        LogEntry {
+            id: id.to_string(),
+            seq: 7,
+            source: source.to_string(),
+            channel: MessageChannel::External,
+            type_name: MessageType::Other("Test".to_string()),
+            content: Some("hello".to_string()),
+            timestamp: 1_700_000_000,
+            severity: Severity::Info,
+            attributes: Vec::new(),
+        }
+    }
+
+    fn line(id: &str, source: &str) -> String {
+        serde_json::to_string(&entry(id, source)).unwrap()
+    }
+
+    #[test]
+    fn blank_lines_are_skipped_but_still_counted_in_line_numbers() {
+        let input = format!("{}\n\n   \n\r\n{{not json\n\n{}\n", line(ID_A, "a"), line(ID_B, "b"));
+        let parsed = parse_jsonl(input.as_bytes());
+
+        assert_eq!(parsed.entries.len(), 2);
+        assert_eq!(parsed.rejected_count, 1);
+        assert_eq!(parsed.rejected[0].line, 5);
+    }
+
+    #[test]
+    fn rejected_lines_report_their_line_number_and_reason() {
+        let mut zero_timestamp = entry(ID_B, "b");
+        zero_timestamp.timestamp = 0;
+        let input = [
+            line(ID_A, "a"),
+            "[1, 2".to_string(),
+            line("not-a-uuid", "a"),
+            line(ID_A, ""),
+            serde_json::to_string(&zero_timestamp).unwrap(),
+        ]
+        .join("\n");
+        let parsed = parse_jsonl(input.as_bytes());
+
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(parsed.rejected_count, 4);
+        let lines: Vec<u64> = parsed.rejected.iter().map(|rejected| rejected.line).collect();
+        assert_eq!(lines, vec![2, 3, 4, 5]);
+        assert!(parsed.rejected[0].error.starts_with("Invalid entry"));
+        assert_eq!(parsed.rejected[1].error, "Invalid id: not-a-uuid");
+        assert_eq!(parsed.rejected[2].error, "Source must not be empty");
+        assert_eq!(parsed.rejected[3].error, "Timestamp must be set");
+    }
+
+    #[test]
+    fn rejections_past_the_report_limit_are_only_counted() {
+        let input = "oops\n".repeat(MAX_REPORTED_REJECTIONS + 20);
+        let parsed = parse_jsonl(input.as_bytes());
+
+        assert_eq!(parsed.rejected_count, MAX_REPORTED_REJECTIONS as u64 + 20);
+        assert_eq!(parsed.rejected.len(), MAX_REPORTED_REJECTIONS);
+        assert_eq!(parsed.rejected.last().unwrap().line, MAX_REPORTED_REJECTIONS as u64);
+    }
+
+    #[test]
+    fn payloads_over_the_limits_are_rejected() {
+        assert!(check_limits(100, 10, 100, 10).is_ok());
+        assert!(matches!(check_limits(101, 1, 100, 10), Err(AppError::BadRequest(_))));
+        assert!(matches!(check_limits(10, 11, 100, 10), Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn only_non_blank_lines_count_as_entries() {
+        let input = format!("{}\n\n  \r\n{}\n{{oops\n", line(ID_A, "a"), line(ID_B, "b"));
+        assert_eq!(count_lines(input.as_bytes()), 3);
+        assert_eq!(count_lines(b""), 0);
+    }
+
+    #[test]
+    fn duplicate_ids_in_the_file_or_already_stored_are_dropped() {
+        let entries = vec![entry(ID_A, "first"), entry(ID_B, "b"), entry(ID_A, "second")];
+        let (kept, dropped) = drop_duplicates(entries.clone(), HashSet::new());
+        assert_eq!(dropped, 1);
+        let sources: Vec<&str> = kept.iter().map(|entry| entry.source.as_str()).collect();
+        assert_eq!(sources, vec!["first", "b"]);
+
+        let (kept, dropped) = drop_duplicates(entries, HashSet::from([ID_B.to_string()]));
+        assert_eq!(dropped, 2);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].source, "first");
+    }
+}
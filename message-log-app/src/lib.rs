@@ -1,18 +1,46 @@
+use std::collections::{HashMap, HashSet};
 use std::time::{SystemTime, UNIX_EPOCH};
 use hyperprocess_macro::hyperprocess;
+use hyperware_app_common::get_server;
 use hyperware_process_lib::{
     LazyLoadBlob, get_blob, last_blob,
     http::server::{
-        HttpBindingConfig, HttpServer, HttpServerRequest, StatusCode, 
-        WsMessageType, WsBindingConfig, send_response, send_ws_push
+        HttpBindingConfig, HttpServer, HttpServerRequest,
+        WsMessageType, WsBindingConfig, send_ws_push
     },
     logging::{error, info, init_logging, Level},
+    our, print_to_terminal,
+    sqlite::Sqlite,
     Address, Binding, SaveOptions
 };
 use serde::{Serialize, Deserialize};
-use anyhow::anyhow;
+use uuid::Uuid;
+mod access;
+mod archive;
+mod audit;
+mod db;
+mod error;
+mod export;
+mod forward;
+mod history;
+mod import;
+mod metrics;
+mod ring;
+mod search;
+mod segments;
+mod stats;
+mod terminal;
+mod timers;
 mod types;
-use types::{MessageChannel, MessageType, LogEntry, StatusResponse, HistoryResponse, SuccessResponse, ErrorResponse};
+mod ws;
+use access::Access;
+use timers::TimerKind;
+use types::{
+    AppConfig, AppError, ConfigUpdate, MessageChannel, MessageType, LogEntry, Severity, StatusResponse,
+    HistoryQuery, HistoryResponse, SuccessResponse, ClearResponse, ExportFormat, ExportResponse,
+    ImportSource, ImportResponse, DownloadResponse, SearchQuery, SearchResponse, StatsResponse, ErrorResponse, WsEvent,
+    ExternalLogMessage, EntryAck, BatchResponse,
+};
 
 wit_bindgen::generate!({
     path: "target/wit",
@@ -21,35 +49,130 @@ wit_bindgen::generate!({
     additional_derives: [serde::Deserialize, serde::Serialize, process_macros::SerdeJsonInto],
 });
 
-// Configuration for the application
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AppConfig {
-    /// Maximum number of messages to keep in history
-    pub max_history: usize,
-    /// Whether to log message content
-    pub log_content: bool,
-}
+/// Shortest status broadcast interval we will arm a timer for
+const MIN_STATUS_INTERVAL_MS: u64 = 1_000;
 
-impl Default for AppConfig {
-    fn default() -> Self {
-        Self {
-            max_history: 100,
-            log_content: true,
-        }
-    }
-}
+/// Largest `max_history` the config API accepts
+const MAX_HISTORY_LIMIT: u64 = 10_000_000;
+
+/// Most structured attributes a single entry may carry
+const MAX_ATTRIBUTES: usize = 64;
+
+/// Most messages a single batch may carry
+const MAX_BATCH_SIZE: usize = 1_000;
+
+/// Page size used when replaying missed entries to a resuming subscriber
+const REPLAY_PAGE_SIZE: u64 = 1_000;
+
+/// Shortest retention interval we will arm a timer for
+const MIN_RETENTION_INTERVAL_MS: u64 = 1_000;
+
+/// Smallest segment size rotation may be configured with
+const MIN_SEGMENT_BYTES: u64 = 1_024;
+
+/// Most recent entries kept in memory to answer unfiltered history pages
+const RECENT_CAPACITY: usize = 10_000;
+
+/// Most entries allowed past `max_history` before a trim runs
+const MAX_TRIM_BATCH: u64 = 1_000;
+
+/// Settings keys for the state written through to the database as it changes
+const CONFIG_SETTING: &str = "config";
+const LAST_CLEAR_SETTING: &str = "last_clear";
+const CURRENT_SEGMENT_SETTING: &str = "current_segment";
 
 /// Represents the application state
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AppState {
-    /// Tracks message history for all channels
-    pub message_history: Vec<LogEntry>,
+    /// Handle to the SQLite database holding the message history
+    #[serde(skip)]
+    pub db: Option<Sqlite>,
     /// Message counts by channel
     pub message_counts: Vec<(MessageChannel, usize)>,
     /// Configuration settings
     pub config: AppConfig,
-    /// Connected WebSocket clients (channel_id -> path)
-    pub connected_clients: Vec<(u32, String)>,
+    /// Connected WebSocket clients; sockets do not survive a restart
+    #[serde(skip)]
+    pub connected_clients: Vec<ClientInfo>,
+    /// Live tail subscriptions (channel_id -> filter); these die with the socket
+    #[serde(skip)]
+    pub subscriptions: Vec<(u32, LogSubscription)>,
+    /// Status, retention and forwarding timers currently armed
+    #[serde(skip)]
+    pub timers: timers::Timers,
+    /// Sequence number the next log entry will get
+    pub next_seq: u64,
+    /// The most recent clear, while it can still be undone
+    pub last_clear: Option<LastClear>,
+    /// Segment file currently receiving evicted entries
+    pub current_segment: Option<segments::SegmentInfo>,
+    /// Newest live entries, oldest first; rebuilt from the database on start
+    #[serde(skip)]
+    pub recent: ring::RingBuffer<LogEntry>,
+    /// Number of live entries in the database
+    #[serde(skip)]
+    pub live_count: u64,
+    /// Rolling per-minute and per-hour message counts; saved with the state only, so a restart may lose the last few seconds
+    pub stats: stats::Stats,
+    /// Counters exposed on /metrics
+    #[serde(skip)]
+    pub metrics: metrics::Metrics,
+    /// Entries waiting in the database for the forwarding target; counted on start
+    #[serde(skip)]
+    pub forward_queued: u64,
+    /// Whether a batch is on its way to the forwarding target
+    #[serde(skip)]
+    pub forward_in_flight: bool,
+    /// Failed flushes in a row; drives the retry backoff
+    #[serde(skip)]
+    pub forward_failures: u32,
+}
+
+/// What is needed to undo a clear
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastClear {
+    /// Tag carried by the soft-deleted rows
+    pub clear_id: u64,
+    /// Seconds since epoch after which the clear is final
+    pub undo_until: u64,
+    pub cleared: u64,
+    pub archive_path: String,
+    /// Channel counts as they were before the clear
+    pub message_counts: Vec<(MessageChannel, usize)>,
+}
+
+/// Bookkeeping for a single connected WebSocket client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientInfo {
+    pub channel_id: u32,
+    pub path: String,
+    /// When the connection was first seen, which can trail the real open (see `sync_clients`)
+    pub connected_at: u64,
+    /// When the client last sent us anything
+    pub last_seen: u64,
+    /// Frames received from the client
+    pub messages_received: u64,
+    /// Frames pushed to the client
+    pub messages_sent: u64,
+}
+
+/// Filter a WebSocket client subscribed with; `None` matches everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogSubscription {
+    pub channel: Option<MessageChannel>,
+    pub type_name: Option<MessageType>,
+}
+
+impl LogSubscription {
+    /// Whether this subscription wants the given event
+    fn matches(&self, event: &WsEvent) -> bool {
+        match event {
+            WsEvent::LogEntry(entry) => {
+                self.channel.as_ref().map_or(true, |c| *c == entry.channel)
+                    && self.type_name.as_ref().map_or(true, |t| *t == entry.type_name)
+            }
+        }
+    }
 }
 
 // Helper function to get current timestamp
@@ -60,7 +183,75 @@ fn get_timestamp() -> u64 {
         .as_secs()
 }
 
-// Helper function to log a message and update counts
+// Helper function to get current timestamp in milliseconds
+fn get_timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+// Helper function to pick a jitter in 0..=max_ms; good enough for spreading ticks
+fn jitter_ms(max_ms: u64) -> u64 {
+    if max_ms == 0 {
+        return 0;
+    }
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos() as u64;
+    nanos % (max_ms + 1)
+}
+
+// Helper function to describe each changed config field as "field: old -> new"
+fn config_diff(before: &AppConfig, after: &AppConfig) -> Vec<String> {
+    let mut changes = Vec::new();
+    let mut diff = |field: &str, old: String, new: String| {
+        if old != new {
+            changes.push(format!("{}: {} -> {}", field, old, new));
+        }
+    };
+    
+    diff("max_history", before.max_history.to_string(), after.max_history.to_string());
+    diff("log_content", before.log_content.to_string(), after.log_content.to_string());
+    diff("status_interval_ms", before.status_interval_ms.to_string(), after.status_interval_ms.to_string());
+    diff("status_jitter_ms", before.status_jitter_ms.to_string(), after.status_jitter_ms.to_string());
+    diff("status_paused", before.status_paused.to_string(), after.status_paused.to_string());
+    diff("access.read", before.access.read.join(","), after.access.read.join(","));
+    diff("access.write", before.access.write.join(","), after.access.write.join(","));
+    diff("access.admin", before.access.admin.join(","), after.access.admin.join(","));
+    diff("access.http_auth", before.access.http_auth.to_string(), after.access.http_auth.to_string());
+    diff(
+        "retention",
+        serde_json::to_string(&before.retention).unwrap_or_default(),
+        serde_json::to_string(&after.retention).unwrap_or_default(),
+    );
+    diff(
+        "rotation",
+        serde_json::to_string(&before.rotation).unwrap_or_default(),
+        serde_json::to_string(&after.rotation).unwrap_or_default(),
+    );
+    diff(
+        "forwarding",
+        serde_json::to_string(&before.forwarding).unwrap_or_default(),
+        serde_json::to_string(&after.forwarding).unwrap_or_default(),
+    );
+    
+    changes
+}
+
+// Helper function to check caller-supplied attributes before they are logged
+fn validate_attributes(attributes: &[(String, String)]) -> Result<(), AppError> {
+    if attributes.len() > MAX_ATTRIBUTES {
+        return Err(AppError::BadRequest(format!("At most {} attributes are allowed per entry", MAX_ATTRIBUTES)));
+    }
+    if attributes.iter().any(|(key, _)| key.is_empty()) {
+        return Err(AppError::BadRequest("Attribute keys must not be empty".to_string()));
+    }
+    Ok(())
+}
+
+// Helper function to log a message and update counts; a failed write is only reported
 fn log_message(
     state: &mut AppState,
     source: String,
@@ -68,25 +259,245 @@ fn log_message(
     message_type: MessageType,
     content: Option<String>,
 ) {
-    // Add to message history
-    state.message_history.push(LogEntry {
+    if let Err(e) = log_structured(state, source, channel, message_type, content, Severity::Info, Vec::new()) {
+        error!("Failed to store log entry: {}", e);
+    }
+}
+
+// Helper function to log a message with a severity and structured attributes
+fn log_structured(
+    state: &mut AppState,
+    source: String,
+    channel: MessageChannel,
+    message_type: MessageType,
+    content: Option<String>,
+    severity: Severity,
+    attributes: Vec<(String, String)>,
+) -> Result<(), AppError> {
+    let entry = new_entry(state, source, channel, message_type, content, severity, attributes);
+    store_entries(state, vec![entry]).map(|_| ())
+}
+
+// Helper function to build an entry; `store_entries` gives it its sequence number
+fn new_entry(
+    state: &AppState,
+    source: String,
+    channel: MessageChannel,
+    message_type: MessageType,
+    content: Option<String>,
+    severity: Severity,
+    attributes: Vec<(String, String)>,
+) -> LogEntry {
+    LogEntry {
+        id: Uuid::new_v4().to_string(),
+        seq: 0,
         source,
-        channel: format!("{:?}", channel),
-        type_name: format!("{:?}", message_type),
+        channel,
+        type_name: message_type,
         content: if state.config.log_content { content } else { None },
         timestamp: get_timestamp(),
-    });
+        severity,
+        attributes,
+    }
+}
+
+// Helper function to store new entries (see `persist_entries`), then forward them
+// and tail them out to subscribers; returns the sequence number of the first one.
+fn store_entries(state: &mut AppState, entries: Vec<LogEntry>) -> Result<u64, AppError> {
+    let (first_seq, entries) = persist_entries(state, entries)?;
+    state.queue_for_forwarding(&entries);
+    
+    // Only push to sockets that are still open
+    if !state.subscriptions.is_empty() {
+        state.sync_clients();
+    }
+    for entry in entries {
+        // Tail the new entry out to every matching subscriber
+        let event = WsEvent::LogEntry(entry);
+        let subscribers: Vec<u32> = state.subscriptions
+            .iter()
+            .filter(|(_, subscription)| subscription.matches(&event))
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        for client_id in subscribers {
+            state.send_to_client(client_id, &event);
+        }
+    }
+    
+    Ok(first_seq)
+}
+
+// Helper function to persist entries all-or-nothing, then count and trim them.
+// Entries are numbered from `next_seq` in order, which only advances once they are stored;
+// returns the sequence number of the first one and the stored entries.
+// Imports stop here: history from elsewhere is neither forwarded nor live-tailed.
+fn persist_entries(state: &mut AppState, mut entries: Vec<LogEntry>) -> Result<(u64, Vec<LogEntry>), AppError> {
+    let first_seq = state.next_seq;
+    for (offset, entry) in entries.iter_mut().enumerate() {
+        entry.seq = first_seq + offset as u64;
+    }
+    if let Err(e) = state.db().and_then(|db| db::insert_entries(db, &entries).map_err(AppError::from)) {
+        state.metrics.record_dropped(entries.len() as u64);
+        return Err(e);
+    }
+    state.next_seq = first_seq + entries.len() as u64;
+    state.live_count += entries.len() as u64;
+    for entry in &entries {
+        state.recent.push(entry.clone());
+    }
+    if let Err(e) = state.evict_overflow() {
+        error!("Failed to trim log history: {}", e);
+    }
+    
+    let now = get_timestamp();
+    for entry in &entries {
+        // Update message count for this channel
+        state.increment_channel_count(entry.channel);
+        state.stats.record(entry, now);
+        state.metrics.record_stored(entry);
+    }
+    
+    Ok((first_seq, entries))
+}
+
+// Helper function to log a batch of caller-supplied messages atomically.
+// Every message is validated first; one bad message rejects the whole batch.
+fn log_batch(
+    state: &mut AppState,
+    source: String,
+    channel: MessageChannel,
+    messages: Vec<ExternalLogMessage>,
+) -> Result<BatchResponse, AppError> {
+    if messages.len() > MAX_BATCH_SIZE {
+        state.metrics.record_rejected("batch_too_large", messages.len() as u64);
+        return Err(AppError::BadRequest(format!("At most {} messages are allowed per batch", MAX_BATCH_SIZE)));
+    }
+    
+    // A caller-chosen id must be a UUID and unique within the batch
+    let mut batch_ids: HashSet<String> = HashSet::new();
+    let errors: Vec<Option<String>> = messages
+        .iter()
+        .map(|m| {
+            if let Some(id) = &m.id {
+                if Uuid::parse_str(id).is_err() {
+                    return Some(format!("Invalid id: {}", id));
+                }
+                if !batch_ids.insert(id.clone()) {
+                    return Some(format!("Duplicate id in batch: {}", id));
+                }
+            }
+            validate_attributes(&m.attributes).err().map(|e| e.message().to_string())
+        })
+        .collect();
+    if errors.iter().any(Option::is_some) {
+        state.metrics.record_rejected("validation", messages.len() as u64);
+        return Ok(BatchResponse {
+            success: false,
+            message: "Batch rejected; no messages were logged".to_string(),
+            acks: errors
+                .into_iter()
+                .enumerate()
+                .map(|(index, error)| EntryAck {
+                    index: index as u64,
+                    accepted: false,
+                    id: None,
+                    seq: None,
+                    error,
+                })
+                .collect(),
+        });
+    }
     
-    // Update message count for this channel
-    state.increment_channel_count(channel);
+    // Messages whose id is already stored were logged by an earlier attempt at this batch
+    let batch_ids: Vec<String> = batch_ids.into_iter().collect();
+    let stored: HashMap<String, u64> = if batch_ids.is_empty() {
+        HashMap::new()
+    } else {
+        db::existing_seqs(state.db()?, &batch_ids)?
+    };
+    
+    let mut entries: Vec<LogEntry> = Vec::new();
+    let mut logged: Vec<(String, Option<u64>)> = Vec::new(); // Id, and seq if already stored
+    for m in messages {
+        if let Some(seq) = m.id.as_ref().and_then(|id| stored.get(id)) {
+            logged.push((m.id.unwrap_or_default(), Some(*seq)));
+            continue;
+        }
+        let mut entry = new_entry(
+            state,
+            source.clone(),
+            channel,
+            MessageType::Other(m.message_type),
+            Some(m.content),
+            m.severity.unwrap_or_default(),
+            m.attributes,
+        );
+        if let Some(id) = m.id {
+            entry.id = id;
+        }
+        logged.push((entry.id.clone(), None));
+        entries.push(entry);
+    }
+    let new_count = entries.len();
+    
+    // Nothing was written if the store failed, so the caller can retry the whole batch
+    let mut next_seq = match store_entries(state, entries) {
+        Ok(first_seq) => first_seq,
+        Err(e) => {
+            error!("Failed to store log batch: {}", e);
+            return Err(e);
+        }
+    };
+    let acks: Vec<EntryAck> = logged
+        .into_iter()
+        .enumerate()
+        .map(|(index, (id, seq))| EntryAck {
+            index: index as u64,
+            accepted: true,
+            id: Some(id),
+            seq: Some(seq.unwrap_or_else(|| {
+                next_seq += 1;
+                next_seq - 1
+            })),
+            error: None,
+        })
+        .collect();
     
-    // Trim history if needed
-    if state.message_history.len() > state.config.max_history {
-        state.message_history.remove(0);
+    let duplicates = acks.len() - new_count;
+    Ok(BatchResponse {
+        success: true,
+        message: if duplicates == 0 {
+            format!("{} messages logged successfully", new_count)
+        } else {
+            format!("{} messages logged successfully, {} were already logged", new_count, duplicates)
+        },
+        acks,
+    })
+}
+
+// Helper function to send a JSON payload to a WebSocket client
+fn send_ws_json<T: Serialize>(channel_id: u32, payload: &T) {
+    match serde_json::to_string(payload) {
+        Ok(json) => send_ws_push(
+            channel_id,
+            WsMessageType::Text,
+            LazyLoadBlob {
+                mime: Some("application/json".to_string()),
+                bytes: json.into_bytes(),
+            },
+        ),
+        Err(e) => error!("Failed to serialize WebSocket payload for {}: {:?}", channel_id, e),
     }
 }
 
 impl AppState {
+    /// The log database, or `Unavailable` if it failed to open
+    fn db(&self) -> Result<&Sqlite, AppError> {
+        self.db
+            .as_ref()
+            .ok_or_else(|| AppError::Unavailable("Log database not open".to_string()))
+    }
+
     /// Increment count for a channel
     pub fn increment_channel_count(&mut self, channel: MessageChannel) {
         if let Some(count) = self.message_counts.iter_mut().find(|(ch, _)| *ch == channel) {
@@ -98,47 +509,616 @@ impl AppState {
 
     /// Add a client connection
     pub fn add_client(&mut self, channel_id: u32, path: String) {
-        self.connected_clients.push((channel_id, path));
+        let now = get_timestamp();
+        self.connected_clients.push(ClientInfo {
+            channel_id,
+            path,
+            connected_at: now,
+            last_seen: now,
+            messages_received: 0,
+            messages_sent: 0,
+        });
+    }
+
+    /// Remove a client connection, returning its final bookkeeping
+    pub fn remove_client(&mut self, channel_id: u32) -> Option<ClientInfo> {
+        let index = self.connected_clients.iter().position(|c| c.channel_id == channel_id)?;
+        Some(self.connected_clients.remove(index))
+    }
+
+    /// Get a client's bookkeeping entry
+    pub fn get_client_mut(&mut self, channel_id: u32) -> Option<&mut ClientInfo> {
+        self.connected_clients.iter_mut().find(|c| c.channel_id == channel_id)
     }
 
-    /// Remove a client connection
-    pub fn remove_client(&mut self, channel_id: u32) {
-        self.connected_clients.retain(|(id, _)| *id != channel_id);
+    /// Send a JSON payload to a client and count it against that client
+    pub fn send_to_client<T: Serialize>(&mut self, channel_id: u32, payload: &T) {
+        send_ws_json(channel_id, payload);
+        if let Some(client) = self.get_client_mut(channel_id) {
+            client.messages_sent += 1;
+        }
+    }
+
+    /// Register a newly opened WebSocket connection
+    fn handle_ws_open(&mut self, channel_id: u32, path: String) {
+        self.add_client(channel_id, path.clone());
+        
+        log_message(
+            self,
+            format!("WebSocket:{}", channel_id),
+            MessageChannel::Websocket,
+            MessageType::WebsocketOpen,
+            Some(format!("Client {} connected on {}", channel_id, path)),
+        );
+    }
+
+    /// Forget a closed WebSocket connection
+    fn handle_ws_close(&mut self, channel_id: u32) {
+        self.unsubscribe(channel_id);
+        let Some(client) = self.remove_client(channel_id) else {
+            return;
+        };
+        
+        log_message(
+            self,
+            format!("WebSocket:{}", channel_id),
+            MessageChannel::Websocket,
+            MessageType::WebsocketClose,
+            // The close itself may have been noticed late; report what we know happened
+            Some(format!(
+                "Client {} disconnected, last active {}s after connecting ({} received, {} sent)",
+                channel_id,
+                client.last_seen.saturating_sub(client.connected_at),
+                client.messages_received,
+                client.messages_sent,
+            )),
+        );
+    }
+
+    /// Bring the client table in line with the channels the HTTP server has open.
+    /// The hyperprocess runtime consumes `WebSocketOpen`/`WebSocketClose` itself: it
+    /// records them in the server's channel table and has no hook to pass them on. So
+    /// the table is checked wherever we are about to use the client list (before
+    /// pushing entries, broadcasting or reporting status) rather than on a timer; a
+    /// closed client is never pushed to, even while the status broadcast is paused.
+    /// Open and close times are when we noticed, which can trail the real event.
+    fn sync_clients(&mut self) {
+        let Some(server) = get_server() else {
+            return;
+        };
+        let open: Vec<(u32, String)> = server
+            .get_ws_channels()
+            .into_iter()
+            .flat_map(|(path, ids)| ids.into_iter().map(move |id| (id, path.clone())))
+            .collect();
+        let open_ids: HashSet<u32> = open.iter().map(|(id, _)| *id).collect();
+        
+        let closed: Vec<u32> = self.connected_clients
+            .iter()
+            .map(|c| c.channel_id)
+            .filter(|id| !open_ids.contains(id))
+            .collect();
+        for channel_id in closed {
+            self.handle_ws_close(channel_id);
+        }
+        // Logging an open stores an entry, which can sync again; check the table as it is now
+        for (channel_id, path) in open {
+            if self.get_client_path(channel_id).is_none() {
+                self.handle_ws_open(channel_id, path);
+            }
+        }
+    }
+
+    /// Start (or replace) a live tail subscription for a client
+    pub fn subscribe(&mut self, channel_id: u32, subscription: LogSubscription) {
+        self.unsubscribe(channel_id);
+        self.subscriptions.push((channel_id, subscription));
+    }
+
+    /// Stop a client's live tail subscription
+    pub fn unsubscribe(&mut self, channel_id: u32) {
+        self.subscriptions.retain(|(id, _)| *id != channel_id);
     }
 
     /// Get client path
     pub fn get_client_path(&self, channel_id: u32) -> Option<&str> {
         self.connected_clients
             .iter()
-            .find(|(id, _)| *id == channel_id)
-            .map(|(_, path)| path.as_str())
+            .find(|c| c.channel_id == channel_id)
+            .map(|c| c.path.as_str())
+    }
+
+    /// Arm the next status broadcast, unless broadcasts are paused.
+    /// Any timer armed earlier goes stale and is ignored by `handle_timer`.
+    fn schedule_status_timer(&mut self) {
+        if self.config.status_paused {
+            self.timers.disarm(TimerKind::Status);
+            return;
+        }
+        
+        let delay = self.config.status_interval_ms + jitter_ms(self.config.status_jitter_ms);
+        self.timers.arm(TimerKind::Status, delay);
+    }
+
+    /// Log the status tick and push the status to every connected client
+    fn broadcast_status(&mut self) {
+        // Log the timer message
+        log_message(
+            self,
+            "Timer".to_string(),
+            MessageChannel::Timer,
+            MessageType::TimerTick,
+            Some("Timer event received".to_string()),
+        );
+        info!("Received timer message");
+        
+        // Send status updates to all connected websocket clients
+        let status = match self.get_status_response() {
+            Ok(status) => status,
+            Err(e) => {
+                error!("Failed to build status broadcast: {}", e);
+                return;
+            }
+        };
+        
+        let client_ids: Vec<u32> = self.connected_clients.iter().map(|c| c.channel_id).collect();
+        for client_id in client_ids {
+            self.send_to_client(client_id, &status);
+        }
+    }
+
+    /// Change the status broadcast schedule and re-arm the timer
+    fn update_status_timer(&mut self, source: String, interval_ms: u64, paused: bool) -> Result<SuccessResponse, AppError> {
+        let update = ConfigUpdate {
+            status_interval_ms: Some(interval_ms),
+            status_paused: Some(paused),
+            ..ConfigUpdate::default()
+        };
+        self.update_config(source, update)?;
+        
+        Ok(SuccessResponse {
+            success: true,
+            message: if paused {
+                "Status broadcasts paused".to_string()
+            } else {
+                format!("Status broadcasts every {}ms", interval_ms)
+            },
+        })
+    }
+
+    /// Validate and apply a config update, logging what changed.
+    /// Nothing is applied unless every field in the update is valid.
+    fn update_config(&mut self, source: String, update: ConfigUpdate) -> Result<AppConfig, AppError> {
+        let before = self.config.clone();
+        let mut after = before.clone();
+        if let Some(max_history) = update.max_history {
+            after.max_history = max_history;
+        }
+        if let Some(log_content) = update.log_content {
+            after.log_content = log_content;
+        }
+        if let Some(interval_ms) = update.status_interval_ms {
+            after.status_interval_ms = interval_ms;
+        }
+        if let Some(jitter_ms) = update.status_jitter_ms {
+            after.status_jitter_ms = jitter_ms;
+        }
+        if let Some(paused) = update.status_paused {
+            after.status_paused = paused;
+        }
+        if let Some(access) = update.access {
+            after.access = access;
+        }
+        if let Some(retention) = update.retention {
+            after.retention = retention;
+        }
+        if let Some(rotation) = update.rotation {
+            after.rotation = rotation;
+        }
+        if let Some(forwarding) = update.forwarding {
+            after.forwarding = forwarding;
+        }
+        
+        if after.max_history == 0 || after.max_history > MAX_HISTORY_LIMIT {
+            return Err(AppError::BadRequest(format!("max_history must be between 1 and {}", MAX_HISTORY_LIMIT)));
+        }
+        if after.status_interval_ms < MIN_STATUS_INTERVAL_MS {
+            return Err(AppError::BadRequest(format!("status_interval_ms must be at least {}", MIN_STATUS_INTERVAL_MS)));
+        }
+        if after.status_jitter_ms > after.status_interval_ms {
+            return Err(AppError::BadRequest("status_jitter_ms must not exceed status_interval_ms".to_string()));
+        }
+        after.access.validate()?;
+        if after.retention.interval_ms < MIN_RETENTION_INTERVAL_MS {
+            return Err(AppError::BadRequest(format!("retention.interval_ms must be at least {}", MIN_RETENTION_INTERVAL_MS)));
+        }
+        if after.rotation.max_segments == 0 {
+            return Err(AppError::BadRequest("rotation.max_segments must be at least 1".to_string()));
+        }
+        if after.rotation.max_segment_bytes < MIN_SEGMENT_BYTES {
+            return Err(AppError::BadRequest(format!("rotation.max_segment_bytes must be at least {}", MIN_SEGMENT_BYTES)));
+        }
+        let mut retained_channels = HashSet::new();
+        if !after.retention.channels.iter().all(|c| retained_channels.insert(c.channel)) {
+            return Err(AppError::BadRequest("retention.channels lists a channel twice".to_string()));
+        }
+        after.forwarding.validate()?;
+        
+        let changes = config_diff(&before, &after);
+        if changes.is_empty() {
+            return Ok(after);
+        }
+        
+        self.config = after.clone();
+        self.save_setting(CONFIG_SETTING, &self.config);
+        self.recent.set_capacity(self.recent_capacity());
+        if before.status_interval_ms != after.status_interval_ms
+            || before.status_jitter_ms != after.status_jitter_ms
+            || before.status_paused != after.status_paused
+        {
+            self.schedule_status_timer();
+        }
+        if before.retention.interval_ms != after.retention.interval_ms {
+            self.schedule_retention_timer();
+        }
+        if before.access.http_auth != after.access.http_auth {
+            self.apply_http_auth();
+        }
+        if before.forwarding != after.forwarding {
+            self.apply_forwarding_policy();
+        }
+        
+        log_message(
+            self,
+            source,
+            MessageChannel::Internal,
+            MessageType::Other("ConfigChanged".to_string()),
+            Some(changes.join(", ")),
+        );
+        
+        Ok(after)
     }
 
     /// Clear message counts
     pub fn clear_counts(&mut self) {
         self.message_counts.clear();
+        self.reset_stored_counts();
+    }
+
+    /// Archive every stored entry to a VFS file, then soft-delete them and reset the counts.
+    /// Only the most recent clear can be undone; starting a new one makes the previous final.
+    pub fn clear_stored_history(&mut self) -> Result<ClearResponse, AppError> {
+        let db = self.db()?;
+        db::purge_cleared(db, None)?;
+        
+        let clear_id = get_timestamp_ms();
+        let (archive_path, cleared) = archive::archive_live_entries(db, clear_id)
+            .map_err(|e| AppError::Unavailable(format!("Failed to archive entries before clearing: {}", e)))?;
+        db::soft_clear(db, clear_id)?;
+        self.recent.clear();
+        self.live_count = 0;
+        
+        let undo_until = get_timestamp() + self.config.retention.undo_window_secs;
+        self.last_clear = Some(LastClear {
+            clear_id,
+            undo_until,
+            cleared,
+            archive_path: archive_path.clone(),
+            message_counts: std::mem::take(&mut self.message_counts),
+        });
+        self.save_setting(LAST_CLEAR_SETTING, &self.last_clear);
+        self.reset_stored_counts();
+        
+        Ok(ClearResponse {
+            success: true,
+            message: "History cleared successfully".to_string(),
+            cleared,
+            archive_path,
+            undo_until,
+        })
+    }
+    
+    /// Restore the entries and counts removed by the most recent clear, if it is still undoable
+    pub fn undo_last_clear(&mut self) -> Result<SuccessResponse, AppError> {
+        let last_clear = match &self.last_clear {
+            Some(last_clear) if get_timestamp() <= last_clear.undo_until => last_clear.clone(),
+            Some(_) => return Err(AppError::NotFound("The undo window for the last clear has passed".to_string())),
+            None => return Err(AppError::NotFound("There is no clear to undo".to_string())),
+        };
+        db::restore_cleared(self.db()?, last_clear.clear_id)?;
+        self.last_clear = None;
+        self.save_setting(LAST_CLEAR_SETTING, &self.last_clear);
+        self.reload_recent()?;
+        
+        // Entries logged since the clear keep their counts
+        if let Err(e) = db::add_channel_counts(self.db()?, &last_clear.message_counts) {
+            error!("Failed to restore stored channel counts: {:?}", e);
+        }
+        for (channel, count) in last_clear.message_counts {
+            match self.message_counts.iter_mut().find(|(ch, _)| *ch == channel) {
+                Some(current) => current.1 += count,
+                None => self.message_counts.push((channel, count)),
+            }
+        }
+        
+        Ok(SuccessResponse {
+            success: true,
+            message: format!("Restored {} entries", last_clear.cleared),
+        })
     }
     
-    /// Get status response
-    fn get_status_response(&self) -> StatusResponse {
-        // Convert message counts to simplified format
-        let channel_stats: Vec<(String, u64)> = self.message_counts
+    /// Arm the next retention run; any retention timer armed earlier goes stale
+    fn schedule_retention_timer(&mut self) {
+        self.timers.arm(TimerKind::Retention, self.config.retention.interval_ms);
+    }
+    
+    /// Apply the retention policy and purge a clear whose undo window has passed
+    fn enforce_retention(&mut self) -> Result<(), AppError> {
+        let now = get_timestamp();
+        let policy = self.config.retention.clone();
+        let db = self.db()?;
+        let before = db::count(db)?;
+        
+        if let Some(max_age_secs) = policy.max_age_secs {
+            db::delete_older_than(db, now.saturating_sub(max_age_secs), None)?;
+        }
+        for limits in &policy.channels {
+            if let Some(max_age_secs) = limits.max_age_secs {
+                db::delete_older_than(db, now.saturating_sub(max_age_secs), Some(&limits.channel))?;
+            }
+            if let Some(max_count) = limits.max_count {
+                db::trim_channel(db, &limits.channel, max_count)?;
+            }
+        }
+        let removed = before.saturating_sub(db::count(db)?);
+        self.metrics.record_evicted("retention", removed);
+        if removed > 0 {
+            self.reload_recent()?;
+        }
+        
+        let expired_clear = self.last_clear.as_ref().is_some_and(|c| now > c.undo_until);
+        if expired_clear {
+            db::purge_cleared(self.db()?, None)?;
+            self.last_clear = None;
+            self.save_setting(LAST_CLEAR_SETTING, &self.last_clear);
+        }
+        
+        if removed > 0 || expired_clear {
+            log_message(
+                self,
+                "Retention".to_string(),
+                MessageChannel::Internal,
+                MessageType::Other("RetentionApplied".to_string()),
+                Some(format!(
+                    "Removed {} expired entries{}",
+                    removed,
+                    if expired_clear { "; last clear is now final" } else { "" },
+                )),
+            );
+        }
+        
+        Ok(())
+    }
+    
+    /// Get status response, counting only clients whose sockets are still open
+    fn get_status_response(&mut self) -> Result<StatusResponse, AppError> {
+        self.sync_clients();
+        let channel_stats: Vec<(MessageChannel, u64)> = self.message_counts
             .iter()
-            .map(|(k, v)| (format!("{:?}", k), *v as u64))
+            .map(|(k, v)| (*k, *v as u64))
             .collect();
 
-        StatusResponse {
+        Ok(StatusResponse {
             client_count: self.connected_clients.len() as u64,
-            message_count: self.message_history.len() as u64,
+            message_count: self.live_count,
             channel_stats,
+            rate_per_minute: self.stats.rate_per_minute(get_timestamp()),
+        })
+    }
+    
+    /// Get the rolling throughput statistics
+    fn get_stats_response(&self) -> StatsResponse {
+        self.stats.summary(get_timestamp())
+    }
+    
+    /// Look up a single entry by id
+    fn get_entry_response(&self, id: &str) -> Result<LogEntry, AppError> {
+        db::get_entry(self.db()?, id)?
+            .ok_or_else(|| AppError::NotFound(format!("No log entry with id {}", id)))
+    }
+
+    /// Send every stored entry after `since_seq` that matches the subscription, oldest first
+    fn replay_since(&mut self, channel_id: u32, subscription: &LogSubscription, since_seq: u64) -> Result<(), AppError> {
+        let mut query = HistoryQuery {
+            channel: subscription.channel,
+            type_name: subscription.type_name.clone(),
+            since_seq: Some(since_seq),
+            limit: Some(REPLAY_PAGE_SIZE),
+            ..HistoryQuery::default()
+        };
+        
+        loop {
+            let page = self.get_history_response(&query)?;
+            for entry in page.entries {
+                self.send_to_client(channel_id, &WsEvent::LogEntry(entry));
+            }
+            match page.next_cursor {
+                Some(seq) => query.since_seq = Some(seq),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Validate the attributes of a single message, counting it as rejected if they are invalid
+    fn check_attributes(&mut self, attributes: &[(String, String)]) -> Result<(), AppError> {
+        let result = validate_attributes(attributes);
+        if result.is_err() {
+            self.metrics.record_rejected("validation", 1);
+        }
+        result
+    }
+
+    /// Get one page of full-text search hits, best first
+    fn search_response(&self, query: &SearchQuery) -> Result<SearchResponse, AppError> {
+        let expression = search::match_expression(&query.text)?;
+        let (hits, next_offset) = db::search_entries(self.db()?, &expression, query)?;
+        Ok(SearchResponse { hits, next_offset })
+    }
+
+    /// Write every entry matching the query to an export file
+    fn write_export(&self, query: &HistoryQuery, format: ExportFormat) -> Result<ExportResponse, AppError> {
+        let (path, file_name, entries) = export::export_entries(self.db()?, query, format, get_timestamp_ms())
+            .map_err(|e| AppError::Unavailable(format!("Failed to write export: {}", e)))?;
+        
+        Ok(ExportResponse {
+            success: true,
+            path,
+            file_name,
+            entries,
+        })
+    }
+
+    /// Append the entries of a JSON Lines file, keeping their ids, timestamps and sources.
+    /// Entries whose id is already stored are skipped, so a failed import can simply be rerun.
+    fn import_history_from(&mut self, source: &ImportSource) -> Result<ImportResponse, AppError> {
+        let bytes = import::read_source(source)?;
+        let parsed = import::parse_jsonl(&bytes);
+        self.metrics.record_rejected("import", parsed.rejected_count);
+        
+        let ids: Vec<String> = parsed.entries.iter().map(|e| e.id.clone()).collect();
+        let stored = db::existing_ids(self.db()?, &ids)?;
+        let (mut entries, duplicates) = import::drop_duplicates(parsed.entries, stored);
+        
+        // Imported entries get fresh local sequence numbers, in file order
+        let imported = entries.len() as u64;
+        while !entries.is_empty() {
+            let rest = entries.split_off(entries.len().min(MAX_BATCH_SIZE));
+            persist_entries(self, entries)?;
+            entries = rest;
+        }
+        
+        Ok(ImportResponse {
+            success: parsed.rejected_count == 0,
+            imported,
+            duplicates,
+            rejected_count: parsed.rejected_count,
+            rejected: parsed.rejected,
+        })
+    }
+
+    /// Move live entries beyond `max_history` out of the database, into the
+    /// current segment file when rotation is on. Nothing is deleted unless it was archived.
+    /// Runs only once the overflow reaches ~1% of `max_history`, so each trim removes a batch.
+    fn evict_overflow(&mut self) -> Result<(), AppError> {
+        let max_history = self.config.max_history;
+        let slack = (max_history / 100).clamp(1, MAX_TRIM_BATCH);
+        if self.live_count < max_history + slack {
+            return Ok(());
+        }
+        
+        let db = self.db()?.clone();
+        if self.config.rotation.enabled {
+            let evicted = db::overflow_entries(&db, max_history)?;
+            let result = segments::append(&mut self.current_segment, &self.config.rotation, &evicted, get_timestamp_ms());
+            // Saved after every append so the size survives a restart and rollover still happens on time
+            self.save_setting(CURRENT_SEGMENT_SETTING, &self.current_segment);
+            result.map_err(|e| AppError::Unavailable(format!("Failed to write segment: {}", e)))?;
+        }
+        db::trim(&db, max_history)?;
+        self.metrics.record_evicted("max_history", self.live_count - max_history);
+        self.live_count = max_history;
+        Ok(())
+    }
+    
+    /// How many recent entries to keep in memory; never more than the database holds
+    fn recent_capacity(&self) -> usize {
+        RECENT_CAPACITY.min(self.config.max_history as usize)
+    }
+    
+    /// Rebuild the in-memory recent entries and the live count from the database
+    fn reload_recent(&mut self) -> Result<(), AppError> {
+        let db = self.db()?.clone();
+        let capacity = self.recent_capacity();
+        let mut query = HistoryQuery {
+            limit: Some(u64::MAX),
+            ..HistoryQuery::default()
+        };
+        let mut newest_first = Vec::new();
+        while newest_first.len() < capacity {
+            let (entries, next_cursor) = db::query_entries(&db, &query)?;
+            newest_first.extend(entries);
+            match next_cursor {
+                Some(seq) => query.cursor = Some(seq),
+                None => break,
+            }
+        }
+        newest_first.truncate(capacity);
+        
+        self.recent = ring::RingBuffer::with_capacity(capacity);
+        for entry in newest_first.into_iter().rev() {
+            self.recent.push(entry);
+        }
+        self.live_count = db::count(&db)?;
+        Ok(())
+    }
+    
+    /// Write a setting through to the database; the rest of the state is saved periodically
+    fn save_setting<T: Serialize>(&self, key: &str, value: &T) {
+        let Some(db) = &self.db else {
+            return;
+        };
+        if let Err(e) = db::save_setting(db, key, value) {
+            error!("Failed to save {}: {:?}", key, e);
         }
     }
     
-    /// Get history response
-    fn get_history_response(&self) -> HistoryResponse {
-        HistoryResponse {
-            entries: self.message_history.clone(),
+    // Helper function to zero the channel counts `insert_entries` keeps in the database
+    fn reset_stored_counts(&self) {
+        let Some(db) = &self.db else {
+            return;
+        };
+        if let Err(e) = db::reset_channel_counts(db) {
+            error!("Failed to reset stored channel counts: {:?}", e);
+        }
+    }
+    
+    // Helper function to restore the settings written through by `save_setting`, and the channel counts
+    fn load_settings(&mut self) -> anyhow::Result<()> {
+        let Some(db) = self.db.clone() else {
+            return Ok(());
+        };
+        if let Some(config) = db::load_setting(&db, CONFIG_SETTING)? {
+            self.config = config;
+        }
+        if let Some(last_clear) = db::load_setting(&db, LAST_CLEAR_SETTING)? {
+            self.last_clear = last_clear;
+        }
+        if let Some(current_segment) = db::load_setting(&db, CURRENT_SEGMENT_SETTING)? {
+            self.current_segment = current_segment;
+        }
+        // Counted with every insert, so never behind the saved state
+        self.message_counts = db::channel_counts(&db)?;
+        if let Some(next_seq) = db::load_setting::<u64>(&db, db::NEXT_SEQ_SETTING)? {
+            self.next_seq = self.next_seq.max(next_seq);
+        }
+        Ok(())
+    }
+
+    /// Get one page of history matching the query
+    fn get_history_response(&self, query: &HistoryQuery) -> Result<HistoryResponse, AppError> {
+        if let Some(page) = history::recent_page(&self.recent, self.live_count, query) {
+            return Ok(page);
+        }
+        let db = self.db()?;
+        if !query.include_archived.unwrap_or(false) {
+            let (entries, next_cursor) = db::query_entries(db, query)?;
+            return Ok(HistoryResponse { entries, next_cursor });
         }
+        history::merged_page(
+            query,
+            |query| Ok(db::query_entries(db, query)?),
+            |query| Ok(segments::query_entries(query)?),
+        )
     }
 }
 
@@ -148,22 +1128,62 @@ impl AppState {
     endpoints = vec![
         Binding::Http { 
             path: "/api/status", 
-            config: HttpBindingConfig::new(false, false, false, None) 
+            config: HttpBindingConfig::new(true, false, false, None) 
+        },
+        Binding::Http { 
+            path: "/api/stats", 
+            config: HttpBindingConfig::new(true, false, false, None) 
         },
         Binding::Http { 
             path: "/api/history", 
-            config: HttpBindingConfig::new(false, false, false, None) 
+            config: HttpBindingConfig::new(true, false, false, None) 
+        },
+        Binding::Http { 
+            path: "/api/search", 
+            config: HttpBindingConfig::new(true, false, false, None) 
+        },
+        Binding::Http { 
+            path: "/api/entry", 
+            config: HttpBindingConfig::new(true, false, false, None) 
+        },
+        Binding::Http { 
+            path: "/api/log", 
+            config: HttpBindingConfig::new(true, false, false, None) 
+        },
+        Binding::Http { 
+            path: "/api/batch", 
+            config: HttpBindingConfig::new(true, false, false, None) 
         },
         Binding::Http { 
             path: "/api/clear-history", 
-            config: HttpBindingConfig::new(false, false, false, None) 
+            config: HttpBindingConfig::new(true, false, false, None) 
+        },
+        Binding::Http { 
+            path: "/api/undo-clear", 
+            config: HttpBindingConfig::new(true, false, false, None) 
+        },
+        Binding::Http { 
+            path: "/api/config", 
+            config: HttpBindingConfig::new(true, false, false, None) 
+        },
+        Binding::Http { 
+            path: "/api/export", 
+            config: HttpBindingConfig::new(true, false, false, None) 
+        },
+        Binding::Http { 
+            path: "/api/download", 
+            config: HttpBindingConfig::new(true, false, false, None) 
+        },
+        Binding::Http { 
+            path: "/api/import", 
+            config: HttpBindingConfig::new(true, false, false, None) 
         },
         Binding::Ws { 
             path: "/", 
             config: WsBindingConfig::default() 
         }
     ],
-    save_config = SaveOptions::EveryMessage,
+    save_config = SaveOptions::EveryNSeconds(30),
     wit_world = "message-log-app-dot-os-v0"
 )]
 impl AppState {
@@ -173,11 +1193,34 @@ impl AppState {
         init_logging(Level::DEBUG, Level::INFO, None, None, None).unwrap();
         info!("Message Log App initialized");
         
-        // Set default configuration
-        self.config = AppConfig {
-            max_history: 100,
-            log_content: true,
-        };
+        // Open the log database
+        match db::open() {
+            Ok(db) => self.db = Some(db),
+            Err(e) => error!("Failed to open log database: {:?}", e),
+        }
+        
+        // State is only saved every few seconds; settings written through since then win
+        if let Err(e) = self.load_settings() {
+            error!("Failed to load saved settings: {:?}", e);
+        }
+        
+        // Never reuse a sequence number; the high-water mark covers entries since rotated out,
+        // the live maximum covers databases written before it was kept
+        let stored_seq = self.db.as_ref().and_then(|db| db::max_seq(db).ok()).unwrap_or(0);
+        self.next_seq = self.next_seq.max(stored_seq + 1);
+        
+        // Warm the in-memory recent entries
+        if let Err(e) = self.reload_recent() {
+            error!("Failed to load recent entries: {}", e);
+        }
+        
+        // Entries the previous run queued for the forwarding target are still waiting
+        if let Some(db) = &self.db {
+            match db::forward_queue_len(db) {
+                Ok(queued) => self.forward_queued = queued,
+                Err(e) => error!("Failed to count queued forwarding entries: {:?}", e),
+            }
+        }
         
         // Log initialization
         log_message(
@@ -187,313 +1230,585 @@ impl AppState {
             MessageType::Other("Initialization".to_string()),
             Some("Application started".to_string()),
         );
+        
+        // Timers armed by a previous run may still fire; they must not match ours
+        self.timers = timers::Timers::starting_at(get_timestamp_ms());
+        
+        // The bindings start authenticated; open them up if the saved policy says so
+        self.apply_http_auth();
+        
+        // Publish /metrics and start its refresh
+        self.publish_metrics();
+        
+        // Start the periodic status broadcast, retention runs and forwarding flushes
+        self.schedule_status_timer();
+        self.schedule_retention_timer();
+        self.schedule_forward_timer();
     }
     
     // HTTP Endpoints with explicit return types
     
     #[http]
-    fn get_status(&mut self) -> StatusResponse {
-        log_message(
-            self,
-            "HTTP:GET".to_string(),
-            MessageChannel::HttpApi,
-            MessageType::HttpGet,
-            Some("Status request".to_string()),
-        );
-        
-        self.get_status_response()
+    fn get_status(&mut self) -> Result<StatusResponse, AppError> {
+        self.instrumented("get_status", |state| {
+            state.authorize_http(Access::Read, "get_status")?;
+            
+            log_message(
+                state,
+                "HTTP:GET".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpGet,
+                Some("Status request".to_string()),
+            );
+            
+            state.get_status_response()
+        })
     }
     
     #[http]
-    fn get_history(&mut self) -> HistoryResponse {
-        log_message(
-            self,
-            "HTTP:GET".to_string(),
-            MessageChannel::HttpApi,
-            MessageType::HttpGet,
-            Some("History request".to_string()),
-        );
-        
-        self.get_history_response()
+    fn get_stats(&mut self) -> Result<StatsResponse, AppError> {
+        self.instrumented("get_stats", |state| {
+            state.authorize_http(Access::Read, "get_stats")?;
+            
+            log_message(
+                state,
+                "HTTP:GET".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpGet,
+                Some("Stats request".to_string()),
+            );
+            
+            Ok(state.get_stats_response())
+        })
     }
     
     #[http]
-    fn clear_history(&mut self) -> SuccessResponse {
-        // Clear the history
-        self.message_history.clear();
-        self.clear_counts();
-        
-        log_message(
-            self,
-            "HTTP:POST".to_string(),
-            MessageChannel::HttpApi,
-            MessageType::HttpPost,
-            Some("History cleared".to_string()),
-        );
-        
-        SuccessResponse {
-            success: true,
-            message: "History cleared successfully".to_string(),
-        }
+    fn get_history(&mut self, query: HistoryQuery) -> Result<HistoryResponse, AppError> {
+        self.instrumented("get_history", |state| {
+            state.authorize_http(Access::Read, "get_history")?;
+            
+            log_message(
+                state,
+                "HTTP:GET".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpGet,
+                Some("History request".to_string()),
+            );
+            
+            state.get_history_response(&query)
+        })
     }
     
     #[http]
-    fn log_custom_message(&mut self, message_type: String, content: String) -> SuccessResponse {
-        // Log a custom message
-        log_message(
-            self,
-            "HTTP:Custom".to_string(),
-            MessageChannel::HttpApi,
-            MessageType::Other(message_type),
-            Some(content),
-        );
-        
-        SuccessResponse {
-            success: true,
-            message: "Custom message logged successfully".to_string(),
-        }
+    fn search_history(&mut self, query: SearchQuery) -> Result<SearchResponse, AppError> {
+        self.instrumented("search_history", |state| {
+            state.authorize_http(Access::Read, "search_history")?;
+            
+            log_message(
+                state,
+                "HTTP:GET".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpGet,
+                Some(format!("Search request: {}", query.text)),
+            );
+            
+            state.search_response(&query)
+        })
+    }
+    
+    #[http]
+    fn get_entry(&mut self, id: String) -> Result<LogEntry, AppError> {
+        self.instrumented("get_entry", |state| {
+            state.authorize_http(Access::Read, "get_entry")?;
+            
+            log_message(
+                state,
+                "HTTP:GET".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpGet,
+                Some(format!("Entry {} request", id)),
+            );
+            
+            state.get_entry_response(&id)
+        })
+    }
+    
+    #[http]
+    fn clear_history(&mut self) -> Result<ClearResponse, AppError> {
+        self.instrumented("clear_history", |state| {
+            state.authorize_http(Access::Admin, "clear_history")?;
+            
+            // Clear the history
+            let response = state.clear_stored_history()?;
+            
+            log_message(
+                state,
+                "HTTP:POST".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpPost,
+                Some(format!("History cleared ({} entries archived to {})", response.cleared, response.archive_path)),
+            );
+            
+            Ok(response)
+        })
+    }
+    
+    #[http]
+    fn undo_clear(&mut self) -> Result<SuccessResponse, AppError> {
+        self.instrumented("undo_clear", |state| {
+            state.authorize_http(Access::Admin, "undo_clear")?;
+            
+            let response = state.undo_last_clear()?;
+            
+            log_message(
+                state,
+                "HTTP:POST".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpPost,
+                Some(format!("Clear undone: {}", response.message)),
+            );
+            
+            Ok(response)
+        })
+    }
+    
+    #[http]
+    fn log_custom_message(
+        &mut self,
+        message_type: String,
+        content: String,
+        severity: Option<Severity>,
+        attributes: Vec<(String, String)>,
+    ) -> Result<SuccessResponse, AppError> {
+        self.instrumented("log_custom_message", |state| {
+            state.authorize_http(Access::Write, "log_custom_message")?;
+            
+            state.check_attributes(&attributes)?;
+            
+            // Log a custom message
+            log_structured(
+                state,
+                "HTTP:Custom".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::Other(message_type),
+                Some(content),
+                severity.unwrap_or_default(),
+                attributes,
+            )?;
+            
+            Ok(SuccessResponse {
+                success: true,
+                message: "Custom message logged successfully".to_string(),
+            })
+        })
+    }
+    
+    #[http]
+    fn log_custom_batch(&mut self, messages: Vec<ExternalLogMessage>) -> Result<BatchResponse, AppError> {
+        self.instrumented("log_custom_batch", |state| {
+            state.authorize_http(Access::Write, "log_custom_batch")?;
+            
+            log_batch(state, "HTTP:Custom".to_string(), MessageChannel::HttpApi, messages)
+        })
+    }
+    
+    #[http]
+    fn set_status_timer(&mut self, interval_ms: u64, paused: bool) -> Result<SuccessResponse, AppError> {
+        self.instrumented("set_status_timer", |state| {
+            state.authorize_http(Access::Admin, "set_status_timer")?;
+            
+            log_message(
+                state,
+                "HTTP:POST".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpPost,
+                Some(format!("Status timer update: {}ms, paused: {}", interval_ms, paused)),
+            );
+            
+            state.update_status_timer("HTTP:POST".to_string(), interval_ms, paused)
+        })
+    }
+    
+    #[http]
+    fn get_config(&mut self) -> Result<AppConfig, AppError> {
+        self.instrumented("get_config", |state| {
+            state.authorize_http(Access::Read, "get_config")?;
+            
+            log_message(
+                state,
+                "HTTP:GET".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpGet,
+                Some("Config request".to_string()),
+            );
+            
+            Ok(state.config.clone())
+        })
+    }
+    
+    #[http]
+    fn set_config(&mut self, update: ConfigUpdate) -> Result<AppConfig, AppError> {
+        self.instrumented("set_config", |state| {
+            state.authorize_http(Access::Admin, "set_config")?;
+            
+            log_message(
+                state,
+                "HTTP:POST".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpPost,
+                Some("Config update request".to_string()),
+            );
+            
+            state.update_config("HTTP:Config".to_string(), update)
+        })
+    }
+    
+    #[http]
+    fn export_history(&mut self, query: HistoryQuery, format: ExportFormat) -> Result<ExportResponse, AppError> {
+        self.instrumented("export_history", |state| {
+            state.authorize_http(Access::Read, "export_history")?;
+            
+            let response = state.write_export(&query, format)?;
+            
+            log_message(
+                state,
+                "HTTP:POST".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpPost,
+                Some(format!("Exported {} entries to {}", response.entries, response.path)),
+            );
+            
+            Ok(response)
+        })
+    }
+    
+    #[http]
+    fn download_export(&mut self, file_name: String) -> Result<DownloadResponse, AppError> {
+        self.instrumented("download_export", |state| {
+            state.authorize_http(Access::Read, "download_export")?;
+            
+            log_message(
+                state,
+                "HTTP:GET".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpGet,
+                Some(format!("Download of {} requested", file_name)),
+            );
+            
+            export::publish_download(&file_name)
+        })
+    }
+    
+    #[http]
+    fn import_history(&mut self, source: ImportSource) -> Result<ImportResponse, AppError> {
+        self.instrumented("import_history", |state| {
+            state.authorize_http(Access::Admin, "import_history")?;
+            
+            let response = state.import_history_from(&source)?;
+            
+            log_message(
+                state,
+                "HTTP:POST".to_string(),
+                MessageChannel::HttpApi,
+                MessageType::HttpPost,
+                Some(format!(
+                    "Imported {} entries ({} duplicates, {} rejected)",
+                    response.imported, response.duplicates, response.rejected_count,
+                )),
+            );
+            
+            Ok(response)
+        })
     }
     
     // WebSocket handling
     
     #[ws]
     fn handle_websocket(&mut self, channel_id: u32, message_type: WsMessageType, blob: LazyLoadBlob) {
-        // Handle WebSocket messages
-        if let Ok(message_str) = std::str::from_utf8(&blob.bytes()) {
-            // Try to parse as JSON
-            if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(message_str) {
-                // Check for various commands
-                if let Some(command) = json_value.get("command").and_then(|c| c.as_str()) {
-                    match command {
-                        "get_status" => {
-                            log_message(
-                                self,
-                                "WebSocket:GetStatus".to_string(),
-                                MessageChannel::Websocket,
-                                MessageType::WebsocketPushA,
-                                Some("Status requested".to_string()),
-                            );
-                            
-                            let status = self.get_status_response();
-                            
-                            // Send status as JSON response
-                            if let Ok(response_json) = serde_json::to_string(&status) {
-                                info!("Sending status to client {}", channel_id);
-                                send_ws_push(
-                                    channel_id,
-                                    WsMessageType::Text,
-                                    LazyLoadBlob {
-                                        mime: Some("application/json".to_string()),
-                                        bytes: response_json.as_bytes().to_vec(),
-                                    },
-                                );
-                            }
-                        },
-                        "get_history" => {
-                            log_message(
-                                self,
-                                "WebSocket:GetHistory".to_string(),
-                                MessageChannel::Websocket,
-                                MessageType::WebsocketPushA,
-                                Some("History requested".to_string()),
-                            );
-                            
-                            let history = self.get_history_response();
-                            
-                            // Send history as JSON response
-                            if let Ok(response_json) = serde_json::to_string(&history) {
-                                info!("Sending history to client {}", channel_id);
-                                send_ws_push(
-                                    channel_id,
-                                    WsMessageType::Text,
-                                    LazyLoadBlob {
-                                        mime: Some("application/json".to_string()),
-                                        bytes: response_json.as_bytes().to_vec(),
-                                    },
-                                );
-                            }
-                        },
-                        "clear_history" => {
-                            // Clear the history
-                            self.message_history.clear();
-                            self.clear_counts();
-                            
-                            log_message(
-                                self,
-                                "WebSocket:Clear".to_string(),
-                                MessageChannel::Websocket,
-                                MessageType::WebsocketPushA,
-                                Some("History cleared".to_string()),
-                            );
-                            
-                            let response = SuccessResponse {
-                                success: true,
-                                message: "History cleared successfully".to_string(),
-                            };
-                            
-                            // Send confirmation as JSON response
-                            if let Ok(response_json) = serde_json::to_string(&response) {
-                                info!("Sending clear confirmation to client {}", channel_id);
-                                send_ws_push(
-                                    channel_id,
-                                    WsMessageType::Text,
-                                    LazyLoadBlob {
-                                        mime: Some("application/json".to_string()),
-                                        bytes: response_json.as_bytes().to_vec(),
-                                    },
-                                );
-                            }
-                        },
-                        "log_message" => {
-                            if let (Some(msg_type), Some(msg_content)) = (
-                                json_value.get("message_type").and_then(|t| t.as_str()),
-                                json_value.get("content").and_then(|c| c.as_str())
-                            ) {
-                                log_message(
-                                    self,
-                                    "WebSocket:Custom".to_string(),
-                                    MessageChannel::Websocket,
-                                    MessageType::WebsocketPushB,
-                                    Some(format!("Type: {}, Content: {}", msg_type, msg_content)),
-                                );
-                                
-                                let response = SuccessResponse {
-                                    success: true,
-                                    message: "Custom message logged successfully".to_string(),
-                                };
-                                
-                                // Send confirmation as JSON response
-                                if let Ok(response_json) = serde_json::to_string(&response) {
-                                    info!("Sending log confirmation to client {}", channel_id);
-                                    send_ws_push(
-                                        channel_id,
-                                        WsMessageType::Text,
-                                        LazyLoadBlob {
-                                            mime: Some("application/json".to_string()),
-                                            bytes: response_json.as_bytes().to_vec(),
-                                        },
-                                    );
-                                }
-                            }
-                        },
-                        _ => {
-                            // Unknown command
-                            let error = ErrorResponse {
-                                success: false,
-                                code: 400,
-                                message: format!("Unknown command: {}", command),
-                            };
-                            
-                            if let Ok(error_json) = serde_json::to_string(&error) {
-                                send_ws_push(
-                                    channel_id,
-                                    WsMessageType::Text,
-                                    LazyLoadBlob {
-                                        mime: Some("application/json".to_string()),
-                                        bytes: error_json.as_bytes().to_vec(),
-                                    },
-                                );
-                            }
-                        }
-                    }
-                }
+        // A close frame ends the connection; nothing else to handle
+        if let WsMessageType::Close = message_type {
+            self.handle_ws_close(channel_id);
+            return;
+        }
+        
+        // Pick up connections we have not seen yet, then count this frame
+        self.sync_clients();
+        if self.get_client_path(channel_id).is_none() {
+            // Not listed by the server yet; WS_PATH is the only WebSocket path we bind
+            self.handle_ws_open(channel_id, access::WS_PATH.to_string());
+        }
+        if let Some(client) = self.get_client_mut(channel_id) {
+            client.last_seen = get_timestamp();
+            client.messages_received += 1;
+        }
+        
+        // Only data frames carry commands
+        if !matches!(message_type, WsMessageType::Text | WsMessageType::Binary) {
+            return;
+        }
+        
+        // Every command gets exactly one reply: its payload or an ErrorResponse
+        let result = ws::parse_command(&blob.bytes()).and_then(|(command, json_value)| {
+            let handler = format!("ws:{}", ws::metrics_name(&command));
+            self.instrumented(&handler, |state| state.run_ws_command(channel_id, &command, &json_value))
+        });
+        match result {
+            Ok(payload) => self.send_to_client(channel_id, &payload),
+            Err(e) => {
+                info!("WebSocket command from client {} failed: {}", channel_id, e);
+                self.send_to_client(channel_id, &ErrorResponse::from(&e));
             }
         }
     }
     
     #[remote]
-    fn external_get_status(&mut self) -> StatusResponse {
-        log_message(
-            self,
-            "External:GetStatus".to_string(),
-            MessageChannel::External,
-            MessageType::ResponseReceived,
-            Some("Status requested externally".to_string()),
-        );
-        
-        self.get_status_response()
+    fn external_get_status(&mut self) -> Result<StatusResponse, AppError> {
+        self.audited("external_get_status", Some(Access::Read), |state, _| {
+            log_message(
+                state,
+                "External:GetStatus".to_string(),
+                MessageChannel::External,
+                MessageType::ResponseReceived,
+                Some("Status requested externally".to_string()),
+            );
+            
+            state.get_status_response()
+        })
     }
     
     #[remote]
-    fn external_get_history(&mut self) -> HistoryResponse {
-        log_message(
-            self,
-            "External:GetHistory".to_string(),
-            MessageChannel::External,
-            MessageType::ResponseReceived,
-            Some("History requested externally".to_string()),
-        );
-        
-        self.get_history_response()
+    fn external_get_stats(&mut self) -> Result<StatsResponse, AppError> {
+        self.audited("external_get_stats", Some(Access::Read), |state, _| {
+            log_message(
+                state,
+                "External:GetStats".to_string(),
+                MessageChannel::External,
+                MessageType::ResponseReceived,
+                Some("Stats requested externally".to_string()),
+            );
+            
+            Ok(state.get_stats_response())
+        })
     }
     
     #[remote]
-    fn external_clear_history(&mut self) -> SuccessResponse {
-        // Clear the history
-        self.message_history.clear();
-        self.clear_counts();
-        
-        log_message(
-            self,
-            "External:ClearHistory".to_string(),
-            MessageChannel::External,
-            MessageType::ResponseReceived,
-            Some("History cleared externally".to_string()),
-        );
-        
-        SuccessResponse {
-            success: true,
-            message: "History cleared successfully".to_string(),
-        }
+    fn external_get_history(&mut self, query: HistoryQuery) -> Result<HistoryResponse, AppError> {
+        self.audited("external_get_history", Some(Access::Read), |state, _| {
+            log_message(
+                state,
+                "External:GetHistory".to_string(),
+                MessageChannel::External,
+                MessageType::ResponseReceived,
+                Some("History requested externally".to_string()),
+            );
+            
+            state.get_history_response(&query)
+        })
+    }
+    
+    #[remote]
+    fn external_search_history(&mut self, query: SearchQuery) -> Result<SearchResponse, AppError> {
+        self.audited("external_search_history", Some(Access::Read), |state, _| {
+            log_message(
+                state,
+                "External:SearchHistory".to_string(),
+                MessageChannel::External,
+                MessageType::ResponseReceived,
+                Some(format!("Search requested externally: {}", query.text)),
+            );
+            
+            state.search_response(&query)
+        })
+    }
+    
+    #[remote]
+    fn external_get_entry(&mut self, id: String) -> Result<LogEntry, AppError> {
+        self.audited("external_get_entry", Some(Access::Read), |state, _| {
+            log_message(
+                state,
+                "External:GetEntry".to_string(),
+                MessageChannel::External,
+                MessageType::ResponseReceived,
+                Some(format!("Entry {} requested externally", id)),
+            );
+            
+            state.get_entry_response(&id)
+        })
+    }
+    
+    #[remote]
+    fn external_clear_history(&mut self) -> Result<ClearResponse, AppError> {
+        self.audited("external_clear_history", Some(Access::Admin), |state, caller| {
+            // Clear the history
+            let response = state.clear_stored_history()?;
+            
+            log_message(
+                state,
+                "External:ClearHistory".to_string(),
+                MessageChannel::External,
+                MessageType::ResponseReceived,
+                Some(format!("History cleared externally by {}", caller)),
+            );
+            
+            Ok(response)
+        })
+    }
+    
+    #[remote]
+    fn external_undo_clear(&mut self) -> Result<SuccessResponse, AppError> {
+        self.audited("external_undo_clear", Some(Access::Admin), |state, caller| {
+            let response = state.undo_last_clear()?;
+            
+            log_message(
+                state,
+                "External:UndoClear".to_string(),
+                MessageChannel::External,
+                MessageType::ResponseReceived,
+                Some(format!("Clear undone externally by {}", caller)),
+            );
+            
+            Ok(response)
+        })
+    }
+    
+    #[remote]
+    fn external_get_config(&mut self) -> Result<AppConfig, AppError> {
+        self.audited("external_get_config", Some(Access::Read), |state, _| {
+            log_message(
+                state,
+                "External:GetConfig".to_string(),
+                MessageChannel::External,
+                MessageType::ResponseReceived,
+                Some("Config requested externally".to_string()),
+            );
+            
+            Ok(state.config.clone())
+        })
+    }
+    
+    #[remote]
+    fn external_set_config(&mut self, update: ConfigUpdate) -> Result<AppConfig, AppError> {
+        self.audited("external_set_config", Some(Access::Admin), |state, caller| {
+            state.update_config(format!("External:{}", caller), update)
+        })
     }
     
     #[local]
     #[remote]
-    fn log_external_message(&mut self, message_type: String, content: String) -> SuccessResponse {
-        // Get the source address
-        let source_address = self.get_source().to_string();
-        
-        log_message(
-            self,
-            format!("External:{}", source_address),
-            MessageChannel::External,
-            MessageType::Other(message_type),
-            Some(content),
-        );
-        
-        SuccessResponse {
-            success: true,
-            message: "Message logged successfully".to_string(),
+    fn external_import_history(&mut self, source: ImportSource) -> Result<ImportResponse, AppError> {
+        self.audited("external_import_history", Some(Access::Admin), |state, caller| {
+            let response = state.import_history_from(&source)?;
+            
+            log_message(
+                state,
+                "External:ImportHistory".to_string(),
+                MessageChannel::External,
+                MessageType::ResponseReceived,
+                Some(format!(
+                    "{} imported {} entries ({} duplicates, {} rejected)",
+                    caller, response.imported, response.duplicates, response.rejected_count,
+                )),
+            );
+            
+            Ok(response)
+        })
+    }
+    
+    #[local]
+    #[remote]
+    fn log_external_message(
+        &mut self,
+        message_type: String,
+        content: String,
+        severity: Option<Severity>,
+        attributes: Vec<(String, String)>,
+    ) -> Result<SuccessResponse, AppError> {
+        self.audited("log_external_message", Some(Access::Write), |state, caller| {
+            state.check_attributes(&attributes)?;
+            
+            log_structured(
+                state,
+                format!("External:{}", caller),
+                MessageChannel::External,
+                MessageType::Other(message_type),
+                Some(content),
+                severity.unwrap_or_default(),
+                attributes,
+            )?;
+            
+            Ok(SuccessResponse {
+                success: true,
+                message: "Message logged successfully".to_string(),
+            })
+        })
+    }
+    
+    #[local]
+    #[remote]
+    fn log_external_batch(&mut self, messages: Vec<ExternalLogMessage>) -> Result<BatchResponse, AppError> {
+        self.audited("log_external_batch", Some(Access::Write), |state, caller| {
+            log_batch(state, format!("External:{}", caller), MessageChannel::External, messages)
+        })
+    }
+    
+    /// Text command from the node terminal, e.g. `log tail 20` through the `log` script
+    /// (`log:message-log-app:template.os`), which prints the reply itself.
+    /// The command is logged on the Terminal channel; for any other sender its output is printed here.
+    #[local]
+    fn terminal_command(&mut self, command: String) -> Result<String, AppError> {
+        let from_script = self.get_source().process.to_string() == access::TERMINAL_SCRIPT;
+        self.audited("terminal_command", None, |state, caller| {
+            log_message(
+                state,
+                format!("Terminal:{}", caller),
+                MessageChannel::Terminal,
+                MessageType::TerminalCommand,
+                Some(command.clone()),
+            );
+            
+            let result = state.run_terminal_command(caller, &command);
+            if !from_script {
+                match &result {
+                    Ok(output) => print_to_terminal(0, output),
+                    Err(e) => print_to_terminal(0, &format!("message-log-app: {}", e)),
+                }
+            }
+            result
+        })
+    }
+    
+    /// Send the next batch of queued entries to the forwarding target.
+    /// Only the app itself calls this, from the forwarding timer. Deliberately not
+    /// `audited`: that would log an entry per flush, and the outcome of each flush is
+    /// already counted in the forwarding metrics.
+    #[local]
+    async fn flush_forwarding(&mut self) -> Result<u64, AppError> {
+        if *self.get_source() != our() {
+            return Err(AppError::Forbidden("flush_forwarding is only called by the app itself".to_string()));
         }
+        
+        self.flush_forward_queue().await
     }
     
     #[timer]
     fn handle_timer(&mut self) {
-        // Log the timer message
-        log_message(
-            self,
-            "Timer".to_string(),
-            MessageChannel::Timer,
-            MessageType::TimerTick,
-            Some("Timer event received".to_string()),
-        );
-        info!("Received timer message");
-        
-        // Send status updates to all connected websocket clients
-        let status = self.get_status_response();
-        
-        if let Ok(status_json) = serde_json::to_string(&status) {
-            for (client_id, _) in &self.connected_clients {
-                // Sending WS push to all connected clients
-                send_ws_push(
-                    *client_id,
-                    WsMessageType::Text,
-                    LazyLoadBlob {
-                        mime: Some("application/json".to_string()),
-                        bytes: status_json.as_bytes().to_vec(),
-                    },
-                );
+        // Each timer carries its kind and generation; ones armed before a reschedule are stale
+        let Some(kind) = timers::fired().and_then(|fired| self.timers.take(fired)) else {
+            return;
+        };
+        match kind {
+            TimerKind::Retention => {
+                self.schedule_retention_timer();
+                if let Err(e) = self.enforce_retention() {
+                    error!("Failed to apply retention policy: {}", e);
+                }
+            }
+            TimerKind::Forward => self.start_forward_flush(),
+            TimerKind::Status => {
+                self.schedule_status_timer();
+                self.broadcast_status();
             }
+            TimerKind::Metrics => self.publish_metrics(),
         }
     }
 }
@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::time::Instant;
+use hyperware_app_common::get_server;
+use hyperware_process_lib::logging::error;
+use crate::db;
+use crate::timers::TimerKind;
+use crate::types::{AppError, LogEntry};
+use crate::AppState;
+
+/// Upper bounds (seconds) of the handler latency histogram buckets
+const LATENCY_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+/// Content type of the Prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Path Prometheus scrapes
+pub const METRICS_PATH: &str = "/metrics";
+
+/// How often the content served on `METRICS_PATH` is re-rendered
+const METRICS_REFRESH_MS: u64 = 15_000;
+
+/// Process-lifetime counters; like any Prometheus counter they restart from zero with the process
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// Messages stored, by (channel, type) storage keys
+    messages: HashMap<(String, String), u64>,
+    /// Entries removed from the live log, by reason
+    evicted: BTreeMap<&'static str, u64>,
+    /// Messages refused before storage, by reason
+    rejected: BTreeMap<&'static str, u64>,
+    /// Messages that passed validation but could not be stored
+    dropped: u64,
+    /// Entries the forwarding target accepted
+    forwarded: u64,
+    /// Entries forwarding gave up on, by reason
+    forward_dropped: BTreeMap<&'static str, u64>,
+    /// Failed forwarding flushes, by reason
+    forward_failures: BTreeMap<&'static str, u64>,
+    /// Latency and outcomes, by handler
+    handlers: BTreeMap<String, HandlerMetrics>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct HandlerMetrics {
+    /// Observations per latency bucket, not cumulative
+    buckets: Vec<u64>,
+    sum_seconds: f64,
+    count: u64,
+    /// Failed calls, by error code
+    errors: BTreeMap<&'static str, u64>,
+}
+
+impl Metrics {
+    pub fn record_stored(&mut self, entry: &LogEntry) {
+        let key = (db::channel_key(&entry.channel), db::type_key(&entry.type_name));
+        *self.messages.entry(key).or_insert(0) += 1;
+    }
+
+    pub fn record_evicted(&mut self, reason: &'static str, count: u64) {
+        if count > 0 {
+            *self.evicted.entry(reason).or_insert(0) += count;
+        }
+    }
+
+    pub fn record_rejected(&mut self, reason: &'static str, count: u64) {
+        if count > 0 {
+            *self.rejected.entry(reason).or_insert(0) += count;
+        }
+    }
+
+    pub fn record_dropped(&mut self, count: u64) {
+        self.dropped += count;
+    }
+
+    pub fn record_forwarded(&mut self, count: u64) {
+        self.forwarded += count;
+    }
+
+    pub fn record_forward_dropped(&mut self, reason: &'static str, count: u64) {
+        if count > 0 {
+            *self.forward_dropped.entry(reason).or_insert(0) += count;
+        }
+    }
+
+    pub fn record_forward_failure(&mut self, reason: &'static str) {
+        *self.forward_failures.entry(reason).or_insert(0) += 1;
+    }
+
+    /// Count one call of `handler` that took `seconds`, failing with `error` if it did
+    pub fn record_call(&mut self, handler: &str, seconds: f64, error: Option<&AppError>) {
+        let metrics = self.handlers.entry(handler.to_string()).or_default();
+        if metrics.buckets.is_empty() {
+            metrics.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            metrics.buckets[index] += 1;
+        }
+        metrics.sum_seconds += seconds;
+        metrics.count += 1;
+        if let Some(error) = error {
+            *metrics.errors.entry(error.code()).or_insert(0) += 1;
+        }
+    }
+}
+
+impl AppState {
+    /// Run a handler body, recording its latency and outcome under `handler`.
+    /// A failed HTTP call is answered with the error's status code.
+    pub fn instrumented<T>(
+        &mut self,
+        handler: &str,
+        body: impl FnOnce(&mut Self) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let started = Instant::now();
+        let result = body(self);
+        self.metrics.record_call(handler, started.elapsed().as_secs_f64(), result.as_ref().err());
+        if let Err(e) = &result {
+            e.set_http_status();
+        }
+        result
+    }
+
+    /// Re-render the metrics into the static binding on `METRICS_PATH` and arm the next refresh.
+    /// The HTTP server answers scrapes itself, so they see the numbers as of the last refresh,
+    /// up to `METRICS_REFRESH_MS` old. The binding needs no login, since scrapers cannot log in:
+    /// anyone who can reach the node can read the counters, including channel, type and handler
+    /// names, but never entry content or sources.
+    pub fn publish_metrics(&mut self) {
+        self.timers.arm(TimerKind::Metrics, METRICS_REFRESH_MS);
+        self.sync_clients();
+        let Some(server) = get_server() else {
+            return;
+        };
+        let published = server.bind_http_static_path(
+            METRICS_PATH,
+            false,
+            false,
+            Some(CONTENT_TYPE.to_string()),
+            self.render_metrics().into_bytes(),
+        );
+        if let Err(e) = published {
+            error!("Failed to publish {}: {:?}", METRICS_PATH, e);
+        }
+    }
+
+    /// All metrics in the Prometheus text exposition format
+    pub fn render_metrics(&self) -> String {
+        let metrics = &self.metrics;
+        let mut out = String::new();
+
+        header(&mut out, "message_log_messages_total", "counter", "Messages stored, by channel and type.");
+        let mut messages: Vec<_> = metrics.messages.iter().collect();
+        messages.sort();
+        for ((channel, type_name), count) in messages {
+            sample(&mut out, "message_log_messages_total", &[("channel", channel.as_str()), ("type", type_name.as_str())], *count);
+        }
+
+        header(&mut out, "message_log_history_entries", "gauge", "Live entries in the history.");
+        sample(&mut out, "message_log_history_entries", &[], self.live_count);
+
+        header(&mut out, "message_log_ws_clients", "gauge", "Connected WebSocket clients.");
+        sample(&mut out, "message_log_ws_clients", &[], self.connected_clients.len() as u64);
+
+        header(&mut out, "message_log_evicted_entries_total", "counter", "Entries removed from the history, by reason.");
+        for (reason, count) in &metrics.evicted {
+            sample(&mut out, "message_log_evicted_entries_total", &[("reason", *reason)], *count);
+        }
+
+        header(&mut out, "message_log_rejected_messages_total", "counter", "Messages refused before storage, by reason.");
+        for (reason, count) in &metrics.rejected {
+            sample(&mut out, "message_log_rejected_messages_total", &[("reason", *reason)], *count);
+        }
+
+        header(&mut out, "message_log_dropped_messages_total", "counter", "Valid messages that could not be stored.");
+        sample(&mut out, "message_log_dropped_messages_total", &[], metrics.dropped);
+
+        header(&mut out, "message_log_forward_queue_entries", "gauge", "Entries waiting to be forwarded.");
+        sample(&mut out, "message_log_forward_queue_entries", &[], self.forward_queued);
+
+        header(&mut out, "message_log_forwarded_messages_total", "counter", "Entries accepted by the forwarding target.");
+        sample(&mut out, "message_log_forwarded_messages_total", &[], metrics.forwarded);
+
+        header(&mut out, "message_log_forward_dropped_messages_total", "counter", "Entries forwarding gave up on, by reason.");
+        for (reason, count) in &metrics.forward_dropped {
+            sample(&mut out, "message_log_forward_dropped_messages_total", &[("reason", *reason)], *count);
+        }
+
+        header(&mut out, "message_log_forward_failures_total", "counter", "Failed forwarding flushes, by reason.");
+        for (reason, count) in &metrics.forward_failures {
+            sample(&mut out, "message_log_forward_failures_total", &[("reason", *reason)], *count);
+        }
+
+        header(&mut out, "message_log_handler_duration_seconds", "histogram", "Handler latency, by handler.");
+        for (handler, calls) in &metrics.handlers {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&calls.buckets) {
+                cumulative += count;
+                let le = bound.to_string();
+                sample(&mut out, "message_log_handler_duration_seconds_bucket", &[("handler", handler.as_str()), ("le", le.as_str())], cumulative);
+            }
+            let labels = [("handler", handler.as_str())];
+            sample(&mut out, "message_log_handler_duration_seconds_bucket", &[("handler", handler.as_str()), ("le", "+Inf")], calls.count);
+            let _ = writeln!(out, "message_log_handler_duration_seconds_sum{} {}", label_set(&labels), calls.sum_seconds);
+            sample(&mut out, "message_log_handler_duration_seconds_count", &labels, calls.count);
+        }
+
+        header(&mut out, "message_log_handler_errors_total", "counter", "Failed handler calls, by handler and error code.");
+        for (handler, calls) in &metrics.handlers {
+            for (code, count) in &calls.errors {
+                sample(&mut out, "message_log_handler_errors_total", &[("handler", handler.as_str()), ("code", *code)], *count);
+            }
+        }
+
+        out
+    }
+}
+
+
+// Write the HELP and TYPE lines of a metric family
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// Write one sample line
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: u64) {
+    let _ = writeln!(out, "{}{} {}", name, label_set(labels), value);
+}
+
+// Render `{name="value",...}`, or nothing without labels
+fn label_set(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label(value)))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+// Escape a label value as the exposition format requires
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MessageChannel, MessageType, Severity};
+
+    fn entry(channel: MessageChannel, type_name: MessageType) -> LogEntry {
+        LogEntry {
+            id: "id".to_string(),
+            seq: 1,
+            source: "test".to_string(),
+            channel,
+            type_name,
+            content: None,
+            timestamp: 1,
+            severity: Severity::Info,
+            attributes: Vec::new(),
+        }
+    }
+
+    // The sample lines of a family, without HELP and TYPE
+    fn samples<'a>(text: &'a str, name: &str) -> Vec<&'a str> {
+        text.lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter(|line| line.split(['{', ' ']).next() == Some(name))
+            .collect()
+    }
+
+    #[test]
+    fn label_values_escape_backslashes_quotes_and_newlines() {
+        assert_eq!(escape_label("plain"), "plain");
+        assert_eq!(escape_label(r#"a\b"c"#), r#"a\\b\"c"#);
+        assert_eq!(escape_label("two\nlines"), "two\\nlines");
+        assert_eq!(label_set(&[]), "");
+        assert_eq!(label_set(&[("a", "1"), ("b", "x\"y")]), r#"{a="1",b="x\"y"}"#);
+    }
+
+    #[test]
+    fn every_family_has_help_and_type() {
+        let text = AppState::default().render_metrics();
+        for family in [
+            "message_log_messages_total",
+            "message_log_history_entries",
+            "message_log_forward_queue_entries",
+            "message_log_handler_duration_seconds",
+        ] {
+            assert!(text.contains(&format!("# HELP {} ", family)), "{} has HELP", family);
+            assert!(text.contains(&format!("# TYPE {} ", family)), "{} has TYPE", family);
+        }
+        assert_eq!(samples(&text, "message_log_history_entries"), vec!["message_log_history_entries 0"]);
+    }
+
+    #[test]
+    fn stored_messages_are_labelled_by_channel_and_type() {
+        let mut state = AppState::default();
+        state.metrics.record_stored(&entry(MessageChannel::External, MessageType::Other("Say \"hi\"".to_string())));
+        state.metrics.record_stored(&entry(MessageChannel::External, MessageType::Other("Say \"hi\"".to_string())));
+        state.metrics.record_stored(&entry(MessageChannel::Timer, MessageType::TimerTick));
+        let text = state.render_metrics();
+        assert_eq!(
+            samples(&text, "message_log_messages_total"),
+            vec![
+                r#"message_log_messages_total{channel="External",type="Other:Say \"hi\""} 2"#,
+                r#"message_log_messages_total{channel="Timer",type="TimerTick"} 1"#,
+            ],
+        );
+    }
+
+    #[test]
+    fn handler_histograms_are_cumulative_and_end_in_inf() {
+        let mut state = AppState::default();
+        state.metrics.record_call("get_status", 0.002, None);
+        state.metrics.record_call("get_status", 0.3, Some(&AppError::Forbidden("no".to_string())));
+        state.metrics.record_call("get_status", 60.0, None);
+        let text = state.render_metrics();
+
+        let buckets = samples(&text, "message_log_handler_duration_seconds_bucket");
+        assert_eq!(buckets.len(), LATENCY_BUCKETS.len() + 1);
+        assert_eq!(buckets[0], r#"message_log_handler_duration_seconds_bucket{handler="get_status",le="0.001"} 0"#);
+        assert_eq!(buckets[1], r#"message_log_handler_duration_seconds_bucket{handler="get_status",le="0.005"} 1"#);
+        assert_eq!(buckets[LATENCY_BUCKETS.len() - 1], r#"message_log_handler_duration_seconds_bucket{handler="get_status",le="5"} 2"#);
+        assert_eq!(buckets[LATENCY_BUCKETS.len()], r#"message_log_handler_duration_seconds_bucket{handler="get_status",le="+Inf"} 3"#);
+        assert_eq!(
+            samples(&text, "message_log_handler_duration_seconds_count"),
+            vec![r#"message_log_handler_duration_seconds_count{handler="get_status"} 3"#],
+        );
+        assert_eq!(
+            samples(&text, "message_log_handler_errors_total"),
+            vec![r#"message_log_handler_errors_total{handler="get_status",code="forbidden"} 1"#],
+        );
+    }
+}
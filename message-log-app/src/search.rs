@@ -0,0 +1,89 @@
+use crate::types::AppError;
+
+/// Most terms a single search may combine
+const MAX_SEARCH_TERMS: usize = 32;
+
+/// Turn user search text into an FTS5 expression matching every term.
+/// Each word or "quoted phrase" is matched as a phrase, so ids like `req-42-a`
+/// stay whole instead of being read as operators; a trailing `*` makes it a prefix.
+pub fn match_expression(text: &str) -> Result<String, AppError> {
+    let mut terms = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let (term, tail) = match rest.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+        };
+        // A star right after the term (or its closing quote) asks for a prefix match
+        let (term, prefix, tail) = match (term.strip_suffix('*'), tail.strip_prefix('*')) {
+            (Some(stem), _) => (stem, true, tail),
+            (None, Some(after_star)) => (term, true, after_star),
+            (None, None) => (term, false, tail),
+        };
+        // Terms without a letter or digit hold no tokens and would match nothing
+        if term.chars().any(char::is_alphanumeric) {
+            let phrase = format!("\"{}\"", term.replace('"', "\"\""));
+            terms.push(if prefix { format!("{}*", phrase) } else { phrase });
+        }
+        rest = tail.trim_start();
+    }
+
+    if terms.is_empty() {
+        return Err(AppError::BadRequest("Search text must contain at least one word".to_string()));
+    }
+    if terms.len() > MAX_SEARCH_TERMS {
+        return Err(AppError::BadRequest(format!("Search text may combine at most {} terms", MAX_SEARCH_TERMS)));
+    }
+    Ok(terms.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_become_phrases_so_operators_stay_literal() {
+        assert_eq!(match_expression("req-42-a").unwrap(), "\"req-42-a\"");
+        assert_eq!(match_expression("  timeout  OR  NOT retry ").unwrap(), "\"timeout\" \"OR\" \"NOT\" \"retry\"");
+        assert_eq!(match_expression("a\"b").unwrap(), "\"a\"\"b\"");
+    }
+
+    #[test]
+    fn quoted_phrases_are_kept_together() {
+        assert_eq!(match_expression("\"connection reset\" peer").unwrap(), "\"connection reset\" \"peer\"");
+        // An unclosed quote runs to the end of the text
+        assert_eq!(match_expression("peer \"connection reset").unwrap(), "\"peer\" \"connection reset\"");
+    }
+
+    #[test]
+    fn trailing_star_makes_a_prefix_match() {
+        assert_eq!(match_expression("time*").unwrap(), "\"time\"*");
+        assert_eq!(match_expression("\"connection res\"*").unwrap(), "\"connection res\"*");
+        assert_eq!(match_expression("\"connection res*\" peer").unwrap(), "\"connection res\"* \"peer\"");
+        // A star on its own is not a term
+        assert_eq!(match_expression("time *").unwrap(), "\"time\"");
+    }
+
+    #[test]
+    fn punctuation_only_terms_are_dropped() {
+        assert_eq!(match_expression("error -- ... \"!!\" warn").unwrap(), "\"error\" \"warn\"");
+        assert!(matches!(match_expression("-- ... *"), Err(AppError::BadRequest(_))));
+        assert!(matches!(match_expression("   "), Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn term_count_is_limited() {
+        let at_limit = vec!["word"; MAX_SEARCH_TERMS].join(" ");
+        assert!(match_expression(&at_limit).is_ok());
+
+        // Dropped punctuation does not count towards the limit
+        let with_noise = format!("{} - -", at_limit);
+        assert!(match_expression(&with_noise).is_ok());
+
+        let over_limit = vec!["word"; MAX_SEARCH_TERMS + 1].join(" ");
+        assert!(matches!(match_expression(&over_limit), Err(AppError::BadRequest(_))));
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+use hyperware_process_lib::{
+    logging::error,
+    vfs::{open_dir, open_file, remove_file, SeekFrom},
+};
+use serde::{Deserialize, Serialize};
+use crate::archive;
+use crate::db;
+use crate::history::{self, matches};
+use crate::types::{HistoryQuery, LogEntry, RotationPolicy};
+
+/// Directory on the app's drive holding rotated segments
+const SEGMENTS_DIR: &str = "segments";
+
+/// Bytes read from a segment file at a time while scanning it
+const READ_CHUNK_BYTES: usize = 1 << 20;
+
+/// The segment evicted entries are currently appended to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentInfo {
+    pub file_name: String,
+    /// Milliseconds since epoch
+    pub created_at: u64,
+    pub size: u64,
+}
+
+/// Append evicted entries to the current segment, rolling over to a new one
+/// (and pruning the oldest) when it has grown too big or too old
+pub fn append(
+    current: &mut Option<SegmentInfo>,
+    policy: &RotationPolicy,
+    entries: &[LogEntry],
+    now_ms: u64,
+) -> anyhow::Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let dir = archive::dir_path(SEGMENTS_DIR)?;
+
+    let expired = |segment: &SegmentInfo| {
+        segment.size >= policy.max_segment_bytes
+            || now_ms.saturating_sub(segment.created_at) >= policy.max_segment_age_secs * 1_000
+    };
+    if current.as_ref().map_or(true, expired) {
+        *current = Some(SegmentInfo {
+            file_name: format!("segment-{}.jsonl", now_ms),
+            created_at: now_ms,
+            size: 0,
+        });
+        prune(&dir, policy.max_segments.saturating_sub(1))?;
+    }
+
+    let Some(segment) = current.as_mut() else {
+        return Ok(());
+    };
+    let bytes = archive::to_jsonl(entries)?;
+    let mut file = open_file(&format!("{}/{}", dir, segment.file_name), true, None)?;
+    file.append(&bytes)?;
+    segment.size += bytes.len() as u64;
+
+    Ok(())
+}
+
+/// One page of archived entries matching `query`, in the same order and with the
+/// same cursor semantics as `db::query_entries`. Segments are streamed in chunks
+/// and only the entries that can still make the page are held in memory.
+pub fn query_entries(query: &HistoryQuery) -> anyhow::Result<(Vec<LogEntry>, Option<u64>)> {
+    let dir = archive::dir_path(SEGMENTS_DIR)?;
+    let names = segment_names(&dir)?;
+    collect_page(query, &names, |name, visit| scan_segment(&format!("{}/{}", dir, name), visit))
+}
+
+// Helper function to build a page from `segments`, oldest first, each read by `scan`
+// the way `scan_segment` reads a file
+fn collect_page<S>(
+    query: &HistoryQuery,
+    segments: &[S],
+    mut scan: impl FnMut(&S, &mut dyn FnMut(LogEntry) -> bool) -> anyhow::Result<()>,
+) -> anyhow::Result<(Vec<LogEntry>, Option<u64>)> {
+    let ascending = query.since_seq.is_some();
+    let ordered: Vec<&S> = if ascending {
+        segments.iter().collect()
+    } else {
+        segments.iter().rev().collect()
+    };
+
+    let limit = db::page_size(query) as usize;
+    let mut page: Vec<LogEntry> = Vec::new();
+    for segment in ordered {
+        // One extra entry tells us whether another page exists
+        let wanted = limit + 1 - page.len();
+        let mut matching: VecDeque<LogEntry> = VecDeque::new();
+        scan(segment, &mut |entry| {
+            if !matches(query, &entry) {
+                return true;
+            }
+            matching.push_back(entry);
+            if ascending {
+                // The oldest matches come first, so the rest of the file is not needed
+                return matching.len() < wanted;
+            }
+            // Newest first: only the last `wanted` matches of the file can make the page
+            if matching.len() > wanted {
+                matching.pop_front();
+            }
+            true
+        })?;
+        if ascending {
+            page.extend(matching);
+        } else {
+            page.extend(matching.into_iter().rev());
+        }
+        if page.len() > limit {
+            break;
+        }
+    }
+
+    Ok(history::into_page(page, limit))
+}
+
+// Feed each entry of a segment file to `visit`, oldest first, reading the file
+// `READ_CHUNK_BYTES` at a time. Stops early once `visit` returns false.
+fn scan_segment(path: &str, visit: &mut dyn FnMut(LogEntry) -> bool) -> anyhow::Result<()> {
+    let mut file = open_file(path, false, None)?;
+    let len = file.metadata()?.len;
+    let mut chunk = vec![0u8; READ_CHUNK_BYTES];
+    let mut pending: Vec<u8> = Vec::new();
+    let mut offset = 0;
+
+    while offset < len {
+        file.seek(SeekFrom::Start(offset))?;
+        let read = file.read_at(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        offset += read as u64;
+        if !feed_lines(&mut pending, &chunk[..read], visit) {
+            return Ok(());
+        }
+    }
+
+    // A last line without a trailing newline
+    if let Ok(entry) = serde_json::from_slice::<LogEntry>(&pending) {
+        visit(entry);
+    }
+    Ok(())
+}
+
+// Add a chunk to the `pending` partial line and visit every entry it completes.
+// Returns false once `visit` asks to stop.
+fn feed_lines(pending: &mut Vec<u8>, chunk: &[u8], visit: &mut dyn FnMut(LogEntry) -> bool) -> bool {
+    pending.extend_from_slice(chunk);
+
+    // Everything up to the last newline is whole lines; the rest waits for the next chunk
+    let Some(end) = pending.iter().rposition(|b| *b == b'\n') else {
+        return true;
+    };
+    for line in pending[..end].split(|b| *b == b'\n') {
+        if let Ok(entry) = serde_json::from_slice::<LogEntry>(line) {
+            if !visit(entry) {
+                return false;
+            }
+        }
+    }
+    pending.drain(..=end);
+    true
+}
+
+// Segment file names, oldest first; the names embed their creation time
+fn segment_names(dir: &str) -> anyhow::Result<Vec<String>> {
+    let paths = open_dir(dir, true, None)?.read()?.into_iter().map(|entry| entry.path);
+    Ok(sorted_segment_names(paths))
+}
+
+// Names of the segment files among `paths`, oldest first
+fn sorted_segment_names(paths: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut names: Vec<String> = paths
+        .into_iter()
+        .filter_map(|path| path.rsplit('/').next().map(str::to_string))
+        .filter(|name| name.starts_with("segment-") && name.ends_with(".jsonl"))
+        .collect();
+    names.sort_by_key(|name| {
+        name.trim_start_matches("segment-")
+            .trim_end_matches(".jsonl")
+            .parse::<u64>()
+            .unwrap_or(0)
+    });
+    names
+}
+
+// Delete the oldest segments so that at most `keep` remain
+fn prune(dir: &str, keep: u64) -> anyhow::Result<()> {
+    let names = segment_names(dir)?;
+    let excess = names.len().saturating_sub(keep as usize);
+    for name in &names[..excess] {
+        if let Err(e) = remove_file(&format!("{}/{}", dir, name), None) {
+            error!("Failed to remove old segment {}: {:?}", name, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MessageChannel, MessageType, Severity};
+
+    fn entry(seq: u64) -> LogEntry {
+        LogEntry {
+            id: format!("entry-{}", seq),
+            seq,
+            source: "test:app:template.os".to_string(),
+            channel: if seq % 2 == 0 { MessageChannel::Internal } else { MessageChannel::External },
+            type_name: MessageType::TimerTick,
+            content: Some(format!("message {}", seq)),
+            timestamp: 1_700_000_000 + seq,
+            severity: Severity::Info,
+            attributes: Vec::new(),
+        }
+    }
+
+    fn seqs(entries: &[LogEntry]) -> Vec<u64> {
+        entries.iter().map(|entry| entry.seq).collect()
+    }
+
+    // Segments as `append` writes them: entries oldest first, across files oldest first
+    fn segments() -> Vec<Vec<LogEntry>> {
+        vec![(1..=4).map(entry).collect(), (5..=8).map(entry).collect(), (9..=10).map(entry).collect()]
+    }
+
+    // Page over in-memory segments, counting how many entries each scan visits
+    fn page(segments: &[Vec<LogEntry>], query: &HistoryQuery) -> ((Vec<LogEntry>, Option<u64>), usize) {
+        let mut visited = 0;
+        let page = collect_page(query, segments, |segment, visit| {
+            for entry in segment.iter().cloned() {
+                visited += 1;
+                if !visit(entry) {
+                    break;
+                }
+            }
+            Ok(())
+        })
+        .unwrap();
+        (page, visited)
+    }
+
+    #[test]
+    fn newest_first_pages_walk_back_across_segments() {
+        let segments = segments();
+        let ((first, cursor), _) = page(&segments, &HistoryQuery { limit: Some(3), ..HistoryQuery::default() });
+        assert_eq!((seqs(&first), cursor), (vec![10, 9, 8], Some(8)));
+
+        let ((second, cursor), _) = page(&segments, &HistoryQuery { limit: Some(3), cursor, ..HistoryQuery::default() });
+        assert_eq!((seqs(&second), cursor), (vec![7, 6, 5], Some(5)));
+
+        let ((last, cursor), _) = page(&segments, &HistoryQuery { limit: Some(5), cursor, ..HistoryQuery::default() });
+        assert_eq!((seqs(&last), cursor), (vec![4, 3, 2, 1], None));
+    }
+
+    #[test]
+    fn oldest_first_pages_stop_reading_once_full() {
+        let segments = segments();
+        let query = HistoryQuery { limit: Some(3), since_seq: Some(2), ..HistoryQuery::default() };
+        let ((entries, cursor), visited) = page(&segments, &query);
+        assert_eq!((seqs(&entries), cursor), (vec![3, 4, 5], Some(5)));
+        // The fourth match (6) only says another page exists; nothing after it is read
+        assert_eq!(visited, 6);
+    }
+
+    #[test]
+    fn filters_apply_within_segments() {
+        let query = HistoryQuery {
+            channel: Some(MessageChannel::External),
+            limit: Some(10),
+            ..HistoryQuery::default()
+        };
+        let ((entries, cursor), _) = page(&segments(), &query);
+        assert_eq!((seqs(&entries), cursor), (vec![9, 7, 5, 3, 1], None));
+    }
+
+    #[test]
+    fn lines_split_across_chunks_are_reassembled() {
+        let bytes = archive::to_jsonl(&(1..=5).map(entry).collect::<Vec<_>>()).unwrap();
+        for chunk_size in [1, 7, 64, bytes.len()] {
+            let mut found = Vec::new();
+            let mut pending = Vec::new();
+            for chunk in bytes.chunks(chunk_size) {
+                assert!(feed_lines(&mut pending, chunk, &mut |entry| {
+                    found.push(entry.seq);
+                    true
+                }));
+            }
+            assert_eq!(found, vec![1, 2, 3, 4, 5], "chunks of {} bytes", chunk_size);
+            assert!(pending.is_empty());
+        }
+    }
+
+    #[test]
+    fn feeding_stops_when_the_visitor_does_and_skips_bad_lines() {
+        let mut bytes = b"not json\n".to_vec();
+        bytes.extend(archive::to_jsonl(&(1..=3).map(entry).collect::<Vec<_>>()).unwrap());
+        let mut found = Vec::new();
+        let more = feed_lines(&mut Vec::new(), &bytes, &mut |entry| {
+            found.push(entry.seq);
+            entry.seq < 2
+        });
+        assert!(!more);
+        assert_eq!(found, vec![1, 2]);
+    }
+
+    #[test]
+    fn segment_names_sort_by_creation_time() {
+        let paths = [
+            "pkg/segments/segment-1700000000100.jsonl",
+            "pkg/segments/export-1.jsonl",
+            "pkg/segments/segment-999.jsonl",
+            "pkg/segments/segment-1700000000020.jsonl",
+            "pkg/segments/segment-5.csv",
+        ];
+        let names = sorted_segment_names(paths.iter().map(|path| path.to_string()));
+        assert_eq!(
+            names,
+            vec!["segment-999.jsonl", "segment-1700000000020.jsonl", "segment-1700000000100.jsonl"],
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::{HashMap, VecDeque};
+use serde::{Deserialize, Serialize};
+use crate::types::{LogEntry, MessageChannel, SourceCount, StatsBucket, StatsResponse};
+
+const MINUTE_SECS: u64 = 60;
+const HOUR_SECS: u64 = 3_600;
+
+/// How many minute and hour buckets the rolling window keeps
+const MINUTE_BUCKETS: u64 = 60;
+const HOUR_BUCKETS: u64 = 24;
+
+/// Complete minutes averaged into the current rates
+const RATE_WINDOW_MINUTES: u64 = 5;
+
+/// Distinct sources tracked per minute; the rest are counted together
+const MAX_SOURCES_PER_BUCKET: usize = 1_000;
+
+/// Name the sources past `MAX_SOURCES_PER_BUCKET` are counted under
+const OTHER_SOURCES: &str = "(other)";
+
+/// How many sources `top_sources` lists
+const TOP_SOURCES: usize = 10;
+
+/// Message counts over a rolling window of minutes and hours, by arrival time
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Stats {
+    minutes: VecDeque<Bucket>,
+    hours: VecDeque<Bucket>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Bucket {
+    counts: StatsBucket,
+    /// Only kept for minute buckets
+    sources: HashMap<String, u64>,
+}
+
+impl Stats {
+    /// Count an entry that arrived at `now` (seconds since epoch)
+    pub fn record(&mut self, entry: &LogEntry, now: u64) {
+        let minute = current_bucket(&mut self.minutes, now, MINUTE_SECS, MINUTE_BUCKETS);
+        minute.add(entry);
+        let tracked = minute.sources.len() < MAX_SOURCES_PER_BUCKET || minute.sources.contains_key(&entry.source);
+        let source = if tracked { entry.source.as_str() } else { OTHER_SOURCES };
+        *minute.sources.entry(source.to_string()).or_insert(0) += 1;
+
+        current_bucket(&mut self.hours, now, HOUR_SECS, HOUR_BUCKETS).add(entry);
+    }
+
+    /// Messages per minute over the last complete minutes
+    pub fn rate_per_minute(&self, now: u64) -> f64 {
+        let total: u64 = self.rate_window(now).map(|bucket| bucket.counts.total).sum();
+        total as f64 / RATE_WINDOW_MINUTES as f64
+    }
+
+    /// Everything in the window, as of `now`
+    pub fn summary(&self, now: u64) -> StatsResponse {
+        let mut channel_rates: Vec<(MessageChannel, f64)> = Vec::new();
+        for bucket in self.rate_window(now) {
+            for (channel, count) in &bucket.counts.channels {
+                let rate = *count as f64 / RATE_WINDOW_MINUTES as f64;
+                match channel_rates.iter_mut().find(|(c, _)| c == channel) {
+                    Some(current) => current.1 += rate,
+                    None => channel_rates.push((*channel, rate)),
+                }
+            }
+        }
+
+        let hour_ago = bucket_start(now, MINUTE_SECS).saturating_sub(HOUR_SECS);
+        let mut sources: HashMap<&str, u64> = HashMap::new();
+        for bucket in self.minutes.iter().filter(|bucket| bucket.counts.start > hour_ago) {
+            for (source, count) in &bucket.sources {
+                *sources.entry(source.as_str()).or_insert(0) += count;
+            }
+        }
+        let mut top_sources: Vec<SourceCount> = sources
+            .into_iter()
+            .map(|(source, count)| SourceCount { source: source.to_string(), count })
+            .collect();
+        top_sources.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.source.cmp(&b.source)));
+        top_sources.truncate(TOP_SOURCES);
+
+        StatsResponse {
+            minutes: series(&self.minutes, now, MINUTE_SECS, MINUTE_BUCKETS),
+            hours: series(&self.hours, now, HOUR_SECS, HOUR_BUCKETS),
+            rate_per_minute: self.rate_per_minute(now),
+            channel_rates,
+            top_sources,
+        }
+    }
+
+    // The complete minutes the current rates are averaged over
+    fn rate_window(&self, now: u64) -> impl Iterator<Item = &Bucket> {
+        let end = bucket_start(now, MINUTE_SECS);
+        let start = end.saturating_sub(RATE_WINDOW_MINUTES * MINUTE_SECS);
+        self.minutes
+            .iter()
+            .filter(move |bucket| bucket.counts.start >= start && bucket.counts.start < end)
+    }
+}
+
+impl Bucket {
+    fn new(start: u64) -> Self {
+        Bucket {
+            counts: empty_bucket(start),
+            sources: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, entry: &LogEntry) {
+        let counts = &mut self.counts;
+        counts.total += 1;
+        match counts.channels.iter_mut().find(|(channel, _)| *channel == entry.channel) {
+            Some(current) => current.1 += 1,
+            None => counts.channels.push((entry.channel, 1)),
+        }
+        match counts.types.iter_mut().find(|(type_name, _)| *type_name == entry.type_name) {
+            Some(current) => current.1 += 1,
+            None => counts.types.push((entry.type_name.clone(), 1)),
+        }
+    }
+}
+
+// Start of the bucket of width `width` that `now` falls in
+fn bucket_start(now: u64, width: u64) -> u64 {
+    now - now % width
+}
+
+fn empty_bucket(start: u64) -> StatsBucket {
+    StatsBucket {
+        start,
+        total: 0,
+        channels: Vec::new(),
+        types: Vec::new(),
+    }
+}
+
+// The bucket `now` falls in, opening it (and dropping buckets that left the window) if needed
+fn current_bucket(buckets: &mut VecDeque<Bucket>, now: u64, width: u64, keep: u64) -> &mut Bucket {
+    let start = bucket_start(now, width);
+    // A clock that went backwards keeps counting into the newest bucket
+    if buckets.back().map_or(true, |bucket| bucket.counts.start < start) {
+        buckets.push_back(Bucket::new(start));
+    }
+    while buckets.front().is_some_and(|bucket| bucket.counts.start + width * keep <= start) {
+        buckets.pop_front();
+    }
+    buckets.back_mut().expect("the window always holds the current bucket")
+}
+
+// Every bucket of the window ending at `now`, oldest first, with empty ones filled in
+fn series(buckets: &VecDeque<Bucket>, now: u64, width: u64, keep: u64) -> Vec<StatsBucket> {
+    let end = bucket_start(now, width);
+    (0..keep)
+        .rev()
+        .filter_map(|age| end.checked_sub(age * width))
+        .map(|start| {
+            buckets
+                .iter()
+                .find(|bucket| bucket.counts.start == start)
+                .map(|bucket| bucket.counts.clone())
+                .unwrap_or_else(|| empty_bucket(start))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MessageType, Severity};
+
+    /// Start of an hour, so minute and hour buckets line up with it
+    const T0: u64 = 1_699_999_200;
+
+    fn entry(source: &str, channel: MessageChannel) -> LogEntry {
+        LogEntry {
+            id: "id".to_string(),
+            seq: 1,
+            source: source.to_string(),
+            channel,
+            type_name: MessageType::TimerTick,
+            content: None,
+            timestamp: T0,
+            severity: Severity::Info,
+            attributes: Vec::new(),
+        }
+    }
+
+    fn record(stats: &mut Stats, count: usize, now: u64) {
+        for _ in 0..count {
+            stats.record(&entry("a", MessageChannel::Internal), now);
+        }
+    }
+
+    #[test]
+    fn entries_land_in_the_bucket_of_their_minute_and_hour() {
+        let mut stats = Stats::default();
+        record(&mut stats, 2, T0 + 5);
+        record(&mut stats, 1, T0 + 59);
+        record(&mut stats, 3, T0 + 60);
+
+        let summary = stats.summary(T0 + 61);
+        assert_eq!(summary.minutes.len(), MINUTE_BUCKETS as usize);
+        assert_eq!(summary.hours.len(), HOUR_BUCKETS as usize);
+        let last_two: Vec<(u64, u64)> = summary.minutes[58..].iter().map(|b| (b.start, b.total)).collect();
+        assert_eq!(last_two, vec![(T0, 3), (T0 + 60, 3)]);
+        let current_hour = summary.hours.last().unwrap();
+        assert_eq!((current_hour.start, current_hour.total), (T0, 6));
+    }
+
+    #[test]
+    fn series_fill_gaps_with_empty_buckets_oldest_first() {
+        let mut stats = Stats::default();
+        record(&mut stats, 1, T0);
+        record(&mut stats, 1, T0 + 180);
+
+        let minutes = stats.summary(T0 + 180).minutes;
+        let starts: Vec<u64> = minutes.iter().map(|b| b.start).collect();
+        assert!(starts.windows(2).all(|pair| pair[1] == pair[0] + MINUTE_SECS));
+        let totals: Vec<u64> = minutes[56..].iter().map(|b| b.total).collect();
+        assert_eq!(totals, vec![1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn buckets_leave_the_window() {
+        let mut stats = Stats::default();
+        record(&mut stats, 4, T0);
+        record(&mut stats, 1, T0 + MINUTE_SECS * MINUTE_BUCKETS);
+
+        assert_eq!(stats.minutes.len(), 1);
+        assert_eq!(stats.hours.len(), 2);
+        let summary = stats.summary(T0 + MINUTE_SECS * MINUTE_BUCKETS);
+        assert_eq!(summary.minutes.iter().map(|b| b.total).sum::<u64>(), 1);
+        assert_eq!(summary.hours.iter().map(|b| b.total).sum::<u64>(), 5);
+    }
+
+    #[test]
+    fn a_clock_going_backwards_counts_into_the_newest_bucket() {
+        let mut stats = Stats::default();
+        record(&mut stats, 1, T0 + 120);
+        record(&mut stats, 1, T0);
+        assert_eq!(stats.minutes.len(), 1);
+        assert_eq!(stats.minutes[0].counts.total, 2);
+    }
+
+    #[test]
+    fn rates_average_the_last_complete_minutes() {
+        let mut stats = Stats::default();
+        // Six minutes ago is outside the window; the current minute is not complete
+        record(&mut stats, 100, T0);
+        record(&mut stats, 10, T0 + 60);
+        record(&mut stats, 5, T0 + 300);
+        stats.record(&entry("b", MessageChannel::External), T0 + 300);
+        record(&mut stats, 50, T0 + 360);
+
+        let now = T0 + 365;
+        assert_eq!(stats.rate_per_minute(now), 16.0 / RATE_WINDOW_MINUTES as f64);
+        let summary = stats.summary(now);
+        assert_eq!(
+            summary.channel_rates,
+            vec![
+                (MessageChannel::Internal, 15.0 / RATE_WINDOW_MINUTES as f64),
+                (MessageChannel::External, 1.0 / RATE_WINDOW_MINUTES as f64),
+            ],
+        );
+    }
+
+    #[test]
+    fn top_sources_are_ranked_and_overflow_is_grouped() {
+        let mut stats = Stats::default();
+        for index in 0..MAX_SOURCES_PER_BUCKET {
+            stats.record(&entry(&format!("source-{:04}", index), MessageChannel::Internal), T0);
+        }
+        for _ in 0..3 {
+            stats.record(&entry("late-1", MessageChannel::Internal), T0 + 1);
+            stats.record(&entry("late-2", MessageChannel::Internal), T0 + 2);
+        }
+        stats.record(&entry("source-0007", MessageChannel::Internal), T0 + 3);
+
+        let top = stats.summary(T0 + 10).top_sources;
+        assert_eq!(top.len(), TOP_SOURCES);
+        assert_eq!((top[0].source.as_str(), top[0].count), (OTHER_SOURCES, 6));
+        assert_eq!((top[1].source.as_str(), top[1].count), ("source-0007", 2));
+        // Ties are broken by name
+        assert_eq!(top[2].source, "source-0000");
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt::Write;
+use crate::access::{Access, Caller};
+use crate::db;
+use crate::types::{AppError, ConfigUpdate, HistoryQuery, LogEntry};
+use crate::AppState;
+
+/// Entries `tail` prints when no count is given, and the most it prints
+const DEFAULT_TAIL: u64 = 10;
+const MAX_TAIL: u64 = 100;
+
+/// Most matches `grep` prints
+const GREP_LIMIT: u64 = 20;
+
+const HELP: &str = "\
+Commands:
+  tail [n]                 newest n entries (default 10, at most 100)
+  grep <pattern>           newest entries whose content contains pattern
+  stats                    counts, rates and busiest sources
+  clear                    archive and clear the history
+  undo                     undo the last clear while its window lasts
+  config                   show the current config
+  config set <key> <value> change max_history, log_content, status_interval_ms,
+                           status_jitter_ms or status_paused
+  help                     this text";
+
+// Operation class of a command; `None` for commands that need no check
+fn command_access(command: &str, args: &[&str]) -> Option<Access> {
+    match command {
+        "tail" | "grep" | "stats" => Some(Access::Read),
+        "config" if matches!(args, [] | ["get"]) => Some(Access::Read),
+        "clear" | "undo" | "config" => Some(Access::Admin),
+        _ => None,
+    }
+}
+
+// One line per entry: seq, time, severity, channel, type, source and content
+fn format_entry(entry: &LogEntry) -> String {
+    format!(
+        "#{} {} {:?} {:?}/{} {}: {}",
+        entry.seq,
+        entry.timestamp,
+        entry.severity,
+        entry.channel,
+        db::type_key(&entry.type_name),
+        entry.source,
+        entry.content.as_deref().unwrap_or("-"),
+    )
+}
+
+// Build a config update from `config set <key> <value>`
+fn parse_config_set(key: &str, value: &str) -> Result<ConfigUpdate, AppError> {
+    let number = || value
+        .parse::<u64>()
+        .map_err(|_| AppError::BadRequest(format!("{} expects a number, got {}", key, value)));
+    let flag = || value
+        .parse::<bool>()
+        .map_err(|_| AppError::BadRequest(format!("{} expects true or false, got {}", key, value)));
+
+    let mut update = ConfigUpdate::default();
+    match key {
+        "max_history" => update.max_history = Some(number()?),
+        "log_content" => update.log_content = Some(flag()?),
+        "status_interval_ms" => update.status_interval_ms = Some(number()?),
+        "status_jitter_ms" => update.status_jitter_ms = Some(number()?),
+        "status_paused" => update.status_paused = Some(flag()?),
+        _ => return Err(AppError::BadRequest(format!("Unknown config key: {}", key))),
+    }
+    Ok(update)
+}
+
+impl AppState {
+    /// Run one command line from the node terminal and return what to print
+    pub(crate) fn run_terminal_command(&mut self, caller: &Caller, line: &str) -> Result<String, AppError> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("help");
+        let args: Vec<&str> = words.collect();
+
+        if let Some(access) = command_access(command, &args) {
+            self.authorize(caller, access, &format!("terminal {}", command))?;
+        }
+
+        match command {
+            "help" => Ok(HELP.to_string()),
+            "tail" => {
+                let count = match args.first() {
+                    Some(count) => count
+                        .parse::<u64>()
+                        .map_err(|_| AppError::BadRequest(format!("tail expects a count, got {}", count)))?,
+                    None => DEFAULT_TAIL,
+                };
+                let query = HistoryQuery {
+                    limit: Some(count.clamp(1, MAX_TAIL)),
+                    ..HistoryQuery::default()
+                };
+                let page = self.get_history_response(&query)?;
+                let lines: Vec<String> = page.entries.iter().rev().map(format_entry).collect();
+                Ok(if lines.is_empty() { "No entries".to_string() } else { lines.join("\n") })
+            }
+            "grep" => {
+                // The pattern is the rest of the line, spaces included
+                let pattern = line.trim_start().strip_prefix("grep").unwrap_or_default().trim();
+                if pattern.is_empty() {
+                    return Err(AppError::BadRequest("Usage: grep <pattern>".to_string()));
+                }
+                let query = HistoryQuery {
+                    content_contains: Some(pattern.to_string()),
+                    limit: Some(GREP_LIMIT),
+                    ..HistoryQuery::default()
+                };
+                let page = self.get_history_response(&query)?;
+                let mut lines: Vec<String> = page.entries.iter().rev().map(format_entry).collect();
+                if page.next_cursor.is_some() {
+                    lines.insert(0, format!("(newest {} matches)", GREP_LIMIT));
+                }
+                Ok(if lines.is_empty() { format!("No entries contain {:?}", pattern) } else { lines.join("\n") })
+            }
+            "stats" => {
+                let status = self.get_status_response()?;
+                let stats = self.get_stats_response();
+                let mut out = String::new();
+                let _ = writeln!(out, "entries: {}  ws clients: {}", status.message_count, status.client_count);
+                let _ = writeln!(out, "rate: {:.1}/min", stats.rate_per_minute);
+                for (channel, count) in &status.channel_stats {
+                    let rate = stats.channel_rates.iter().find(|(c, _)| c == channel).map_or(0.0, |(_, rate)| *rate);
+                    let _ = writeln!(out, "  {:?}: {} ({:.1}/min)", channel, count, rate);
+                }
+                if let Some(target) = self.config.forwarding.target.as_deref().filter(|_| self.config.forwarding.enabled) {
+                    let _ = writeln!(out, "forwarding to {}: {} queued", target, self.forward_queued);
+                }
+                let _ = writeln!(out, "top sources (last hour):");
+                for source in &stats.top_sources {
+                    let _ = writeln!(out, "  {} {}", source.count, source.source);
+                }
+                Ok(out.trim_end().to_string())
+            }
+            "clear" => {
+                let response = self.clear_stored_history()?;
+                Ok(format!(
+                    "Cleared {} entries, archived to {}; run `undo` before {} to restore them",
+                    response.cleared, response.archive_path, response.undo_until,
+                ))
+            }
+            "undo" => Ok(self.undo_last_clear()?.message),
+            "config" => match args.as_slice() {
+                [] | ["get"] => serde_json::to_string_pretty(&self.config)
+                    .map_err(|e| AppError::Internal(format!("Failed to render config: {}", e))),
+                ["set", key, value] => {
+                    let update = parse_config_set(key, value)?;
+                    self.update_config(format!("Terminal:{}", caller), update)?;
+                    Ok(format!("{} set to {}", key, value))
+                }
+                _ => Err(AppError::BadRequest("Usage: config | config set <key> <value>".to_string())),
+            },
+            _ => Err(AppError::BadRequest(format!("Unknown command: {}; try help", command))),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use hyperware_app_common::APP_HELPERS;
+use hyperware_process_lib::{logging::error, timer};
+use serde::{Deserialize, Serialize};
+
+/// What a timer was armed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimerKind {
+    Status,
+    Retention,
+    Forward,
+    Metrics,
+}
+
+/// Context attached to every timer we arm and handed back when it fires
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TimerContext {
+    pub kind: TimerKind,
+    pub generation: u64,
+}
+
+/// The one live timer of each kind. Re-arming or disarming a kind bumps its
+/// generation, so a timer armed before that is recognised as stale when it fires.
+#[derive(Debug, Clone, Default)]
+pub struct Timers {
+    next_generation: u64,
+    armed: Vec<(TimerKind, u64)>,
+}
+
+impl Timers {
+    /// Start counting generations at `first`. Timers armed before a restart still
+    /// fire, so starting from the clock keeps them from matching new ones.
+    pub fn starting_at(first: u64) -> Self {
+        Timers {
+            next_generation: first,
+            armed: Vec::new(),
+        }
+    }
+
+    /// Arm a timer of this kind, replacing any armed earlier
+    pub fn arm(&mut self, kind: TimerKind, delay_ms: u64) {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        self.disarm(kind);
+        self.armed.push((kind, generation));
+
+        let context = TimerContext { kind, generation };
+        match serde_json::to_vec(&context) {
+            Ok(context) => timer::set_timer(delay_ms, Some(context)),
+            Err(e) => error!("Failed to encode timer context: {}", e),
+        }
+    }
+
+    /// Forget the armed timer of this kind; it is ignored when it fires
+    pub fn disarm(&mut self, kind: TimerKind) {
+        self.armed.retain(|(armed, _)| *armed != kind);
+    }
+
+    /// Claim a fired timer: its kind if it is the live one for that kind, `None` if it is stale
+    pub fn take(&mut self, fired: TimerContext) -> Option<TimerKind> {
+        let index = self.armed.iter().position(|(kind, generation)| {
+            *kind == fired.kind && *generation == fired.generation
+        })?;
+        self.armed.remove(index);
+        Some(fired.kind)
+    }
+}
+
+/// The context of the timer being handled, if it is one we armed
+pub fn fired() -> Option<TimerContext> {
+    APP_HELPERS.with(|helpers| {
+        let helpers = helpers.borrow();
+        let context = helpers.current_message.as_ref()?.context()?;
+        serde_json::from_slice(context).ok()
+    })
+}
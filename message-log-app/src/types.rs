@@ -1,23 +1,134 @@
 use serde::{Serialize, Deserialize};
+use crate::access;
 #[derive(Serialize, Deserialize)]
 pub struct StatusResponse {
     pub client_count: u64,
     pub message_count: u64,
-    pub channel_stats: Vec<(String, u64)>,
+    pub channel_stats: Vec<(MessageChannel, u64)>,
+    pub rate_per_minute: f64, // Messages per minute over the last few complete minutes
+}
+
+/// Message counts for one minute or one hour, starting at `start` (seconds since epoch)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsBucket {
+    pub start: u64,
+    pub total: u64,
+    pub channels: Vec<(MessageChannel, u64)>,
+    pub types: Vec<(MessageType, u64)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceCount {
+    pub source: String,
+    pub count: u64,
+}
+
+/// Rolling throughput; every bucket in the window is present, empty ones included
+#[derive(Serialize, Deserialize)]
+pub struct StatsResponse {
+    pub minutes: Vec<StatsBucket>, // Oldest first, the current (partial) minute last
+    pub hours: Vec<StatsBucket>, // Oldest first, the current (partial) hour last
+    pub rate_per_minute: f64,
+    pub channel_rates: Vec<(MessageChannel, f64)>, // Per minute, same window as `rate_per_minute`
+    pub top_sources: Vec<SourceCount>, // Busiest sources over the last hour
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct HistoryResponse {
     pub entries: Vec<LogEntry>,
+    pub next_cursor: Option<u64>, // Pass back as `cursor` (or `since_seq` when resuming) for the next page
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryQuery {
+    pub channel: Option<MessageChannel>,
+    pub type_name: Option<MessageType>,
+    pub source_prefix: Option<String>,
+    pub since: Option<u64>, // Inclusive, seconds since epoch
+    pub until: Option<u64>, // Inclusive, seconds since epoch
+    pub min_severity: Option<Severity>,
+    pub content_contains: Option<String>,
+    pub limit: Option<u64>,
+    pub cursor: Option<u64>, // Only entries with a lower seq, newest first
+    pub since_seq: Option<u64>, // Only entries with a higher seq, oldest first; for resuming
+    pub include_archived: Option<bool>, // Also search rotated segment files once the live log runs out
+}
+
+/// Full-text search over content, source and type name; hits come back best first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchQuery {
+    pub text: String, // Words to match, all of them; "quoted phrases" and trailing * prefixes work
+    pub channel: Option<MessageChannel>,
+    pub type_name: Option<MessageType>,
+    pub source_prefix: Option<String>,
+    pub since: Option<u64>, // Inclusive, seconds since epoch
+    pub until: Option<u64>, // Inclusive, seconds since epoch
+    pub min_severity: Option<Severity>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>, // Hits to skip; ranks are not stable enough for a seq cursor
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SearchHit {
+    pub entry: LogEntry,
+    pub score: f64, // Relevance, higher is better
+    pub snippet: String, // Best matching fragment, matches wrapped in <mark></mark>; not HTML-escaped
 }
 
 #[derive(Serialize, Deserialize)]
+pub struct SearchResponse {
+    pub hits: Vec<SearchHit>,
+    pub next_offset: Option<u64>, // Pass back as `offset` for the next page
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogEntry {
+    pub id: String, // UUID
+    pub seq: u64, // Monotonic across restarts
     pub source: String,
-    pub channel: String, // Simplified from MessageChannel
-    pub type_name: String, // Simplified from MessageType
+    pub channel: MessageChannel,
+    pub type_name: MessageType,
     pub content: Option<String>,
     pub timestamp: u64,
+    pub severity: Severity,
+    pub attributes: Vec<(String, String)>, // Ordered key/value context
+}
+
+/// One message in a batch sent by a remote or HTTP logger
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalLogMessage {
+    /// Caller-chosen UUID. A message whose id is already stored is acknowledged
+    /// without being stored again, so a batch that timed out can be resent.
+    #[serde(default)]
+    pub id: Option<String>,
+    pub message_type: String,
+    pub content: String,
+    pub severity: Option<Severity>,
+    pub attributes: Vec<(String, String)>,
+}
+
+/// Outcome for one message of a batch, matched up by `index`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryAck {
+    pub index: u64,
+    pub accepted: bool,
+    pub id: Option<String>,
+    pub seq: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub success: bool,
+    pub message: String,
+    pub acks: Vec<EntryAck>,
+}
+
+/// Envelope for messages the server pushes to WebSocket clients unprompted
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum WsEvent {
+    LogEntry(LogEntry),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -26,12 +137,272 @@ pub struct SuccessResponse {
     pub message: String,
 }
 
+/// Result of a clear; the entries stay restorable until `undo_until`
+#[derive(Serialize, Deserialize)]
+pub struct ClearResponse {
+    pub success: bool,
+    pub message: String,
+    pub cleared: u64,
+    pub archive_path: String, // VFS file holding a JSON Lines copy of the cleared entries
+    pub undo_until: u64, // Seconds since epoch
+}
+
+/// Where an export was written
+#[derive(Serialize, Deserialize)]
+pub struct ExportResponse {
+    pub success: bool,
+    pub path: String, // Full VFS path
+    pub file_name: String, // Pass to `download_export` to fetch the file over HTTP
+    pub entries: u64,
+}
+
+/// Where `download_export` published an export file; GET `url_path` for the raw bytes
+#[derive(Serialize, Deserialize)]
+pub struct DownloadResponse {
+    pub file_name: String,
+    pub content_type: String, // MIME type the file is served with
+    pub url_path: String, // Relative to the app's base URL
+    pub size: u64, // Bytes
+}
+
+/// Outcome of an import; duplicates are entries whose id was already stored
+#[derive(Serialize, Deserialize)]
+pub struct ImportResponse {
+    pub success: bool,
+    pub imported: u64,
+    pub duplicates: u64,
+    pub rejected_count: u64,
+    pub rejected: Vec<RejectedLine>, // The first rejections only, see `rejected_count`
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectedLine {
+    pub line: u64, // 1-based
+    pub error: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub success: bool,
-    pub code: String,
+    pub code: String, // One of the stable `AppError` codes, e.g. "bad_request"
     pub message: String,
 }
+
+/// Failure returned by every handler, whatever the transport.
+/// Over HTTP it arrives as `{"Err": ...}` in the handler's one reply, with `status_code()` as its status.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AppError {
+    BadRequest(String),
+    NotFound(String),
+    Forbidden(String),
+    Unavailable(String),
+    Internal(String),
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    /// Maximum number of entries to keep in the log database
+    pub max_history: u64,
+    /// Whether to log message content
+    pub log_content: bool,
+    /// Milliseconds between status broadcasts to WebSocket clients
+    pub status_interval_ms: u64,
+    /// Up to this many milliseconds are added to each interval at random
+    pub status_jitter_ms: u64,
+    /// Stop the status broadcasts without forgetting the interval
+    pub status_paused: bool,
+    /// Who may read, write and administer the log
+    pub access: AccessPolicy,
+    /// Automatic clean-up of old entries
+    pub retention: RetentionPolicy,
+    /// Segment files receiving entries evicted by `max_history`
+    pub rotation: RotationPolicy,
+    /// Sending new entries on to a central node
+    pub forwarding: ForwardingPolicy,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            max_history: 100_000,
+            log_content: true,
+            status_interval_ms: 10_000,
+            status_jitter_ms: 1_000,
+            status_paused: false,
+            access: AccessPolicy::default(),
+            retention: RetentionPolicy::default(),
+            rotation: RotationPolicy::default(),
+            forwarding: ForwardingPolicy::default(),
+        }
+    }
+}
+
+/// When to start a new segment file and how many to keep
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RotationPolicy {
+    /// Write evicted entries to segment files instead of dropping them
+    pub enabled: bool,
+    /// Start a new segment once the current one reaches this size
+    pub max_segment_bytes: u64,
+    /// Start a new segment once the current one is this old
+    pub max_segment_age_secs: u64,
+    /// Delete the oldest segments beyond this many
+    pub max_segments: u64,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_segment_bytes: 4 * 1024 * 1024,
+            max_segment_age_secs: 86_400,
+            max_segments: 10,
+        }
+    }
+}
+
+/// Which new entries are sent on to a central message-log-app node, and how.
+/// Entries are queued as they are stored and sent in batches every `flush_interval_ms`;
+/// while the target is unreachable they wait in the queue and the flushes back off.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForwardingPolicy {
+    /// Send matching entries to `target`
+    pub enabled: bool,
+    /// Address of the central node's app, e.g. `central.os@message-log-app:message-log-app:template.os`
+    pub target: Option<String>,
+    /// Channels to forward; empty forwards every channel
+    pub channels: Vec<MessageChannel>,
+    /// Entries below this severity stay local
+    pub min_severity: Severity,
+    /// Most entries sent per batch
+    pub batch_size: u64,
+    /// Milliseconds between flushes of the queue
+    pub flush_interval_ms: u64,
+    /// Most entries held for the target; the oldest are dropped past this
+    pub max_queue: u64,
+}
+
+impl Default for ForwardingPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target: None,
+            channels: Vec::new(),
+            min_severity: Severity::Trace,
+            batch_size: 100,
+            flush_interval_ms: 5_000,
+            max_queue: 10_000,
+        }
+    }
+}
+
+/// Age and count limits enforced every `interval_ms`, on top of `max_history`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Delete entries older than this many seconds
+    pub max_age_secs: Option<u64>,
+    /// Tighter limits for individual channels
+    pub channels: Vec<ChannelRetention>,
+    /// Milliseconds between retention runs
+    pub interval_ms: u64,
+    /// Seconds a clear can be undone before its entries are purged
+    pub undo_window_secs: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_secs: None,
+            channels: Vec::new(),
+            interval_ms: 60_000,
+            undo_window_secs: 600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelRetention {
+    pub channel: MessageChannel,
+    pub max_age_secs: Option<u64>,
+    pub max_count: Option<u64>,
+}
+
+/// Allowlists per operation class. Entries are node names (`alice.os`), full
+/// addresses (`alice.os@proc:pkg:publisher`), process ids on our node (`proc:pkg:publisher`),
+/// `*` for any other node, `local` for any process on our node, `http` for logged-in
+/// HTTP/WebSocket clients and `anonymous` for HTTP/WebSocket clients while `http_auth` is off.
+/// Only the app itself is always allowed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessPolicy {
+    /// May query status, history, entries and config
+    pub read: Vec<String>,
+    /// May append log entries
+    pub write: Vec<String>,
+    /// May clear the log and change the config
+    pub admin: Vec<String>,
+    /// Require a logged-in session on the `/api/*` HTTP bindings
+    pub http_auth: bool,
+}
+
+impl Default for AccessPolicy {
+    /// Anyone may log, local processes and other nodes included; reading and
+    /// administering are left to the node owner over HTTP and the terminal script
+    fn default() -> Self {
+        let owner = vec![access::HTTP_ENTRY.to_string(), access::TERMINAL_SCRIPT.to_string()];
+        Self {
+            read: owner.clone(),
+            write: vec![
+                access::HTTP_ENTRY.to_string(),
+                access::LOCAL_ENTRY.to_string(),
+                access::REMOTE_ENTRY.to_string(),
+            ],
+            admin: owner,
+            http_auth: true,
+        }
+    }
+}
+
+/// Partial config change; fields left as `None` keep their current value
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConfigUpdate {
+    pub max_history: Option<u64>,
+    pub log_content: Option<bool>,
+    pub status_interval_ms: Option<u64>,
+    pub status_jitter_ms: Option<u64>,
+    pub status_paused: Option<bool>,
+    pub access: Option<AccessPolicy>, // Replaces the whole policy
+    pub retention: Option<RetentionPolicy>, // Replaces the whole policy
+    pub rotation: Option<RotationPolicy>, // Replaces the whole policy
+    pub forwarding: Option<ForwardingPolicy>, // Replaces the whole policy
+}
+/// Where the JSON Lines to import come from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ImportSource {
+    Vfs(String), // Path of a file, e.g. one written by an export
+    Upload(String), // The JSON Lines text itself
+    Blob, // The blob attached to the request; for process callers
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    Jsonl,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    Trace,
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum MessageChannel {
     Websocket,
@@ -0,0 +1,321 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use crate::types::{
+    AppError, ConfigUpdate, ExportFormat, HistoryQuery, ImportSource, MessageChannel, MessageType, SearchQuery,
+    Severity, SuccessResponse,
+};
+use crate::access::Access;
+use crate::{log_message, log_structured, validate_attributes, AppState, LogSubscription};
+
+/// Parse a raw WebSocket frame into its command name and the full JSON object
+pub fn parse_command(bytes: &[u8]) -> Result<(String, Value), AppError> {
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| AppError::BadRequest("WebSocket message is not valid UTF-8".to_string()))?;
+    let json_value: Value = serde_json::from_str(text)
+        .map_err(|e| AppError::BadRequest(format!("WebSocket message is not valid JSON: {}", e)))?;
+    let command = json_value
+        .get("command")
+        .and_then(Value::as_str)
+        .ok_or_else(|| AppError::BadRequest("Missing \"command\" field".to_string()))?
+        .to_string();
+
+    Ok((command, json_value))
+}
+
+// Deserialize a field of a command, if present
+fn optional_field<T: DeserializeOwned>(json_value: &Value, field: &str) -> Result<Option<T>, AppError> {
+    json_value
+        .get(field)
+        .map(|value| {
+            serde_json::from_value(value.clone())
+                .map_err(|e| AppError::BadRequest(format!("Invalid {}: {}", field, e)))
+        })
+        .transpose()
+}
+
+// Deserialize a field of a command that must be present
+fn required_field<T: DeserializeOwned>(json_value: &Value, field: &str) -> Result<T, AppError> {
+    optional_field(json_value, field)?
+        .ok_or_else(|| AppError::BadRequest(format!("Missing {}", field)))
+}
+
+// Read the optional "severity" and "attributes" of a command
+fn parse_fields(json_value: &Value) -> Result<(Severity, Vec<(String, String)>), AppError> {
+    let severity = optional_field::<Severity>(json_value, "severity")?.unwrap_or_default();
+    let attributes = optional_field::<Vec<(String, String)>>(json_value, "attributes")?.unwrap_or_default();
+    validate_attributes(&attributes)?;
+
+    Ok((severity, attributes))
+}
+
+// Operation class of a command; `None` for commands that need no check
+fn command_access(command: &str) -> Option<Access> {
+    match command {
+        "get_status" | "get_stats" | "get_history" | "search" | "get_entry" | "get_config" | "subscribe" | "export_history" => Some(Access::Read),
+        "log_message" => Some(Access::Write),
+        "clear_history" | "undo_clear" | "import_history" | "set_status_timer" | "set_config" => Some(Access::Admin),
+        _ => None,
+    }
+}
+
+/// Name a command is recorded under in metrics; unknown commands share one name
+pub fn metrics_name(command: &str) -> &str {
+    if command_access(command).is_some() || command == "unsubscribe" {
+        command
+    } else {
+        "unknown"
+    }
+}
+
+// Turn a reply payload into JSON
+fn reply<T: Serialize>(payload: T) -> Result<Value, AppError> {
+    serde_json::to_value(payload)
+        .map_err(|e| AppError::Internal(format!("Failed to serialize reply: {}", e)))
+}
+
+impl AppState {
+    /// Run one WebSocket command and build the reply for the client
+    pub(crate) fn run_ws_command(
+        &mut self,
+        channel_id: u32,
+        command: &str,
+        json_value: &Value,
+    ) -> Result<Value, AppError> {
+        if let Some(access) = command_access(command) {
+            let caller = self.http_caller();
+            self.authorize(&caller, access, command)?;
+        }
+        
+        match command {
+            "get_status" => {
+                log_message(
+                    self,
+                    "WebSocket:GetStatus".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some("Status requested".to_string()),
+                );
+
+                reply(self.get_status_response()?)
+            }
+            "get_stats" => {
+                log_message(
+                    self,
+                    "WebSocket:GetStats".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some("Stats requested".to_string()),
+                );
+
+                reply(self.get_stats_response())
+            }
+            "get_history" => {
+                // An absent query means "first page, no filters"
+                let query = optional_field::<HistoryQuery>(json_value, "query")?.unwrap_or_default();
+
+                log_message(
+                    self,
+                    "WebSocket:GetHistory".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some("History requested".to_string()),
+                );
+
+                reply(self.get_history_response(&query)?)
+            }
+            "search" => {
+                let query: SearchQuery = required_field(json_value, "query")?;
+
+                log_message(
+                    self,
+                    "WebSocket:Search".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some(format!("Search requested: {}", query.text)),
+                );
+
+                reply(self.search_response(&query)?)
+            }
+            "export_history" => {
+                // An absent query exports everything
+                let query = optional_field::<HistoryQuery>(json_value, "query")?.unwrap_or_default();
+                let format: ExportFormat = required_field(json_value, "format")?;
+                let response = self.write_export(&query, format)?;
+
+                log_message(
+                    self,
+                    "WebSocket:Export".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some(format!("Exported {} entries to {}", response.entries, response.path)),
+                );
+
+                reply(response)
+            }
+            "import_history" => {
+                let source: ImportSource = required_field(json_value, "source")?;
+                let response = self.import_history_from(&source)?;
+
+                log_message(
+                    self,
+                    "WebSocket:Import".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some(format!(
+                        "Imported {} entries ({} duplicates, {} rejected)",
+                        response.imported, response.duplicates, response.rejected_count,
+                    )),
+                );
+
+                reply(response)
+            }
+            "get_entry" => {
+                let id: String = required_field(json_value, "id")?;
+
+                log_message(
+                    self,
+                    "WebSocket:GetEntry".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some(format!("Entry {} requested", id)),
+                );
+
+                reply(self.get_entry_response(&id)?)
+            }
+            "clear_history" => {
+                // Clear the history
+                let response = self.clear_stored_history()?;
+
+                log_message(
+                    self,
+                    "WebSocket:Clear".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some(format!("History cleared ({} entries archived to {})", response.cleared, response.archive_path)),
+                );
+
+                reply(response)
+            }
+            "undo_clear" => {
+                let response = self.undo_last_clear()?;
+
+                log_message(
+                    self,
+                    "WebSocket:UndoClear".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some(format!("Clear undone: {}", response.message)),
+                );
+
+                reply(response)
+            }
+            "log_message" => {
+                let msg_type: String = required_field(json_value, "message_type")?;
+                let msg_content: String = required_field(json_value, "content")?;
+                let (severity, attributes) = parse_fields(json_value)
+                    .inspect_err(|_| self.metrics.record_rejected("validation", 1))?;
+
+                log_structured(
+                    self,
+                    "WebSocket:Custom".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushB,
+                    Some(format!("Type: {}, Content: {}", msg_type, msg_content)),
+                    severity,
+                    attributes,
+                )?;
+
+                reply(SuccessResponse {
+                    success: true,
+                    message: "Custom message logged successfully".to_string(),
+                })
+            }
+            "set_status_timer" => {
+                let interval_ms = optional_field(json_value, "interval_ms")?
+                    .unwrap_or(self.config.status_interval_ms);
+                let paused = optional_field(json_value, "paused")?
+                    .unwrap_or(self.config.status_paused);
+
+                log_message(
+                    self,
+                    "WebSocket:StatusTimer".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some(format!("Status timer update: {}ms, paused: {}", interval_ms, paused)),
+                );
+
+                reply(self.update_status_timer("WebSocket:StatusTimer".to_string(), interval_ms, paused)?)
+            }
+            "get_config" => {
+                log_message(
+                    self,
+                    "WebSocket:GetConfig".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some("Config requested".to_string()),
+                );
+
+                reply(self.config.clone())
+            }
+            "set_config" => {
+                let update: ConfigUpdate = required_field(json_value, "update")?;
+
+                log_message(
+                    self,
+                    "WebSocket:SetConfig".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some("Config update requested".to_string()),
+                );
+
+                reply(self.update_config("WebSocket:Config".to_string(), update)?)
+            }
+            "subscribe" => {
+                // The filter fields sit next to "command"; absent ones match everything
+                let subscription = LogSubscription {
+                    channel: optional_field(json_value, "channel")?,
+                    type_name: optional_field(json_value, "type_name")?,
+                };
+                let since_seq: Option<u64> = optional_field(json_value, "since_seq")?;
+
+                log_message(
+                    self,
+                    "WebSocket:Subscribe".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some(format!("Client {} subscribed: {:?}", channel_id, subscription)),
+                );
+
+                // A resuming client first gets everything it missed; the reply marks the end of the replay
+                if let Some(since_seq) = since_seq {
+                    self.replay_since(channel_id, &subscription, since_seq)?;
+                }
+
+                // Registered after logging so the client's own subscribe entry is not echoed back
+                self.subscribe(channel_id, subscription);
+
+                reply(SuccessResponse {
+                    success: true,
+                    message: "Subscribed to new log entries".to_string(),
+                })
+            }
+            "unsubscribe" => {
+                self.unsubscribe(channel_id);
+
+                log_message(
+                    self,
+                    "WebSocket:Unsubscribe".to_string(),
+                    MessageChannel::Websocket,
+                    MessageType::WebsocketPushA,
+                    Some(format!("Client {} unsubscribed", channel_id)),
+                );
+
+                reply(SuccessResponse {
+                    success: true,
+                    message: "Unsubscribed from new log entries".to_string(),
+                })
+            }
+            _ => Err(AppError::BadRequest(format!("Unknown command: {}", command))),
+        }
+    }
+}
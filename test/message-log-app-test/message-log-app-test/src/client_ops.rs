@@ -1,7 +1,10 @@
 use crate::*;
 use hyperware_process_lib::{Address, Request};
-use serde_json::to_vec;
-use crate::hyperware::process::message_log_app::{HyperApiRequest, HyperApiResponse, CustomMessage};
+use serde::de::DeserializeOwned;
+use serde_json::{json, to_vec, Value};
+use crate::hyperware::process::message_log_app::{
+    AppError, HistoryQuery, HistoryResponse, MessageChannel, StatusResponse, SuccessResponse,
+};
 
 pub fn run_client_ops(log_file: &mut File, client_addresses: &Vec<Address>) -> anyhow::Result<()> {
     for client in client_addresses.iter() {
@@ -9,7 +12,7 @@ pub fn run_client_ops(log_file: &mut File, client_addresses: &Vec<Address>) -> a
         write_log(
             log_file,
             &format!(
-                "Done running client operations for {}, ", client, 
+                "Done running client operations for {}, ", client,
             ),
         )?;
     }
@@ -18,59 +21,61 @@ pub fn run_client_ops(log_file: &mut File, client_addresses: &Vec<Address>) -> a
 }
 
 fn send_client_operation(client: &Address, log_file: &mut File) -> anyhow::Result<()> {
-    let get_status_request = HyperApiRequest::GetStatus;
-    let get_history_request = HyperApiRequest::GetHistory;
-    let message_request = HyperApiRequest::Message(CustomMessage { 
-        message_type: "test".to_string(), 
-        content: "test message".to_string() 
+    // Log a message; the app takes these from local and remote processes alike
+    let message_request = json!({
+        "LogExternalMessage": ("test", "test message", Value::Null, Vec::<(String, String)>::new())
     });
+    send_and_log::<SuccessResponse>(client, "LogExternalMessage", &message_request, log_file)?;
 
-    // Send GetStatus request
-    let status_request_bytes = to_vec(&get_status_request).unwrap();
-    let status_response = Request::to(client.clone())
-        .body(status_request_bytes)
-        .send_and_await_response(10)??
-        .body()
-        .to_vec();
-    match serde_json::from_slice::<HyperApiResponse>(&status_response) {
-        Ok(response) => write_log(log_file, &format!("GetStatus response from client {}: {:?}", client, response))?,
-        Err(e) => {
-            write_log(log_file, &format!("GetStatus error parsing response from client {}: {:?}", client, e))?;
-            write_log(log_file, &format!("Raw response: {}", String::from_utf8_lossy(&status_response)))?;
-        }
+    // Status and history are only served to other nodes
+    if client.node() == our().node() {
+        write_log(log_file, &format!("Skipping remote-only requests to our own node {}", client))?;
+        return Ok(());
     }
 
-    // Send GetHistory request
-    let history_request_bytes = to_vec(&get_history_request).unwrap();
-    let history_response = Request::to(client.clone())
-        .body(history_request_bytes)
-        .send_and_await_response(10)??
-        .body()
-        .to_vec();
-    match serde_json::from_slice::<HyperApiResponse>(&history_response) {
-        Ok(response) => write_log(log_file, &format!("GetHistory response from client {}: {:?}", client, response))?,
-        Err(e) => {
-            write_log(log_file, &format!("GetHistory error parsing response from client {}: {:?}", client, e))?;
-            write_log(log_file, &format!("Raw response: {}", String::from_utf8_lossy(&history_response)))?;
-        }
-    }
+    let status_request = json!({"ExternalGetStatus": {}});
+    send_and_log::<StatusResponse>(client, "ExternalGetStatus", &status_request, log_file)?;
+
+    let history_query = HistoryQuery {
+        channel: Some(MessageChannel::External),
+        type_name: None,
+        source_prefix: None,
+        since: None,
+        until: None,
+        min_severity: None,
+        content_contains: None,
+        limit: Some(10),
+        cursor: None,
+        since_seq: None,
+        include_archived: None,
+    };
+    let history_request = json!({"ExternalGetHistory": history_query});
+    send_and_log::<HistoryResponse>(client, "ExternalGetHistory", &history_request, log_file)?;
+
+    write_log(log_file, &format!("All operations completed for client {}", client))?;
+
+    Ok(())
+}
 
-    // Send Message request
-    let message_request_bytes = to_vec(&message_request).unwrap();
-    let message_response = Request::to(client.clone())
-        .body(message_request_bytes)
+// Helper function to send one request and log the typed reply, or the raw body if it doesn't parse
+fn send_and_log<T: DeserializeOwned + std::fmt::Debug>(
+    client: &Address,
+    name: &str,
+    request: &Value,
+    log_file: &mut File,
+) -> anyhow::Result<()> {
+    let response = Request::to(client.clone())
+        .body(to_vec(request)?)
         .send_and_await_response(10)??
         .body()
         .to_vec();
-    match serde_json::from_slice::<HyperApiResponse>(&message_response) {
-        Ok(response) => write_log(log_file, &format!("Message response from client {}: {:?}", client, response))?,
+    match serde_json::from_slice::<Result<T, AppError>>(&response) {
+        Ok(Ok(response)) => write_log(log_file, &format!("{} response from client {}: {:?}", name, client, response))?,
+        Ok(Err(e)) => write_log(log_file, &format!("{} refused by client {}: {:?}", name, client, e))?,
         Err(e) => {
-            write_log(log_file, &format!("Message error parsing response from client {}: {:?}", client, e))?;
-            write_log(log_file, &format!("Raw response: {}", String::from_utf8_lossy(&message_response)))?;
+            write_log(log_file, &format!("{} error parsing response from client {}: {:?}", name, client, e))?;
+            write_log(log_file, &format!("Raw response: {}", String::from_utf8_lossy(&response)))?;
         }
     }
-
-    write_log(log_file, &format!("All operations completed for client {}", client))?;
-    
     Ok(())
-}
\ No newline at end of file
+}
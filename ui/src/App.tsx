@@ -4,8 +4,10 @@ import viteLogo from "./assets/vite.svg";
 import HyperwareClientApi from "@hyperware-ai/client-api";
 import "./App.css";
 import { 
-  MessageLog, 
-  StatusResponse 
+  LogEntry, 
+  MessageType, 
+  StatusResponse, 
+  WsEvent 
 } from "./types/types";
 import { 
   fetchStatus,
@@ -26,11 +28,15 @@ const WEBSOCKET_URL = import.meta.env.DEV
 
 console.log('WEBSOCKET URL configured as:', WEBSOCKET_URL);
 
+// Unit message types are plain strings; Other carries its own name
+const formatType = (typeName: MessageType) =>
+  typeof typeName === 'string' ? typeName : typeName.Other;
+
 function App() {
   const [customMessage, setCustomMessage] = useState("");
   const [customType, setCustomType] = useState("info");
   const [statusData, setStatusData] = useState<StatusResponse | null>(null);
-  const [historyData, setHistoryData] = useState<MessageLog[]>([]);
+  const [historyData, setHistoryData] = useState<LogEntry[]>([]);
   const [nodeConnected, setNodeConnected] = useState(true);
   const [api, setApi] = useState<HyperwareClientApi | undefined>();
   const [wsMessages, setWsMessages] = useState<string[]>([]);
@@ -52,11 +58,13 @@ function App() {
           uri: WEBSOCKET_URL,
           nodeId: window.our?.node || 'unknown',
           processId: window.our?.process || BASE_URL?.replace("/", "") || 'unknown',
-          onOpen: (_event, _api) => {
+          onOpen: (_event, api) => {
             if (mounted) {
               console.log("WebSocket Connected");
               setWsStatus('connected');
               setNodeConnected(true);
+              // Ask for new entries as they are logged
+              api.send({ data: { command: "subscribe" } });
             }
           },
           onClose: () => {
@@ -76,8 +84,9 @@ function App() {
               
               setWsMessages(prev => [...prev, JSON.stringify(data)]);
               
-              if (data.type === "status_update") {
-                setStatusData(data);
+              // Subscribed clients get each new entry as it is logged
+              if ((data as WsEvent).event === "log_entry") {
+                setHistoryData(prev => [(data as WsEvent).data, ...prev]);
               }
             } catch (error) {
               console.error("Error parsing WebSocket message", error);
@@ -124,14 +133,14 @@ function App() {
 
   const handleFetchHistory = async () => {
     const history = await fetchHistory();
-    setHistoryData(history);
+    setHistoryData(history.entries);
   };
 
   const handleClearHistory = async () => {
-    const success = await clearHistory();
-    if (success) {
+    const cleared = await clearHistory();
+    if (cleared) {
       setHistoryData([]);
-      setWsMessages(prev => [...prev, "History cleared"]);
+      setWsMessages(prev => [...prev, cleared.message]);
     }
   };
 
@@ -142,10 +151,8 @@ function App() {
     if (success) {
       setCustomMessage("");
       setWsMessages(prev => [...prev, `Sent: ${JSON.stringify({
-        CustomMessage: {
-          message_type: customType,
-          content: customMessage
-        }
+        message_type: customType,
+        content: customMessage
       })}`]);
       
       // Refresh data after sending a message
@@ -303,12 +310,12 @@ function App() {
                         </tr>
                       </thead>
                       <tbody>
-                        {historyData.map((msg, index) => (
-                          <tr key={index}>
+                        {historyData.map((msg) => (
+                          <tr key={msg.id}>
                             <td>{new Date(msg.timestamp * 1000).toLocaleTimeString()}</td>
                             <td>{msg.source}</td>
                             <td>{msg.channel}</td>
-                            <td>{typeof msg.message_type === 'string' ? msg.message_type : JSON.stringify(msg.message_type)}</td>
+                            <td>{formatType(msg.type_name)}</td>
                             <td>{msg.content || "-"}</td>
                           </tr>
                         ))}
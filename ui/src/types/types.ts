@@ -1,14 +1,80 @@
 // Define interfaces for response types
-export interface MessageLog {
+export type Severity = "Trace" | "Debug" | "Info" | "Warn" | "Error";
+
+export type MessageChannel =
+  | "Websocket"
+  | "HttpApi"
+  | "Internal"
+  | "External"
+  | "Timer"
+  | "Terminal";
+
+// Unit variants arrive as plain strings, Other as { Other: "..." }
+export type MessageType = string | { Other: string };
+
+export interface LogEntry {
+    id: string;
+    seq: number;
     source: string;
-    channel: string;
-    message_type: string | { [key: string]: any };
-    content?: string;
+    channel: MessageChannel;
+    type_name: MessageType;
+    content?: string | null;
     timestamp: number;
+    severity: Severity;
+    attributes: [string, string][];
   }
-  
+
+export interface HistoryQuery {
+    channel?: MessageChannel | null;
+    type_name?: MessageType | null;
+    source_prefix?: string | null;
+    since?: number | null;
+    until?: number | null;
+    min_severity?: Severity | null;
+    content_contains?: string | null;
+    limit?: number | null;
+    cursor?: number | null;
+    since_seq?: number | null;
+    include_archived?: boolean | null;
+  }
+
+export interface HistoryResponse {
+    entries: LogEntry[];
+    next_cursor?: number | null;
+  }
+
 export interface StatusResponse {
-    connected_clients: number;
+    client_count: number;
     message_count: number;
-    message_counts_by_channel: { [key: string]: number };
-  }
\ No newline at end of file
+    channel_stats: [MessageChannel, number][];
+    rate_per_minute: number;
+  }
+
+export interface SuccessResponse {
+    success: boolean;
+    message: string;
+  }
+
+export interface ClearResponse {
+    success: boolean;
+    message: string;
+    cleared: number;
+    archive_path: string;
+    undo_until: number;
+  }
+
+export type AppError =
+  | { BadRequest: string }
+  | { NotFound: string }
+  | { Forbidden: string }
+  | { Unavailable: string }
+  | { Internal: string };
+
+// Every handler replies with its Result
+export type ApiResult<T> = { Ok: T } | { Err: AppError };
+
+// Pushed to WebSocket subscribers as entries are logged
+export interface WsEvent {
+    event: "log_entry";
+    data: LogEntry;
+  }
@@ -1,88 +1,69 @@
-import { MessageLog, StatusResponse } from "../types/types";
+import {
+  ApiResult,
+  AppError,
+  ClearResponse,
+  HistoryQuery,
+  HistoryResponse,
+  StatusResponse,
+  SuccessResponse
+} from "../types/types";
 
 const BASE_URL = import.meta.env.BASE_URL;
 
+// Describe an AppError the way the server worded it
+const describeError = (error: AppError): string => {
+  const [kind, message] = Object.entries(error)[0];
+  return `${kind}: ${message}`;
+};
+
+// POST a handler call and unwrap its Result; errors carry a non-2xx status and an Err body
+const callApi = async <T>(path: string, request: object): Promise<T> => {
+  const response = await fetch(`${BASE_URL}${path}`, {
+    method: "POST",
+    headers: {
+      "Content-Type": "application/json"
+    },
+    body: JSON.stringify(request)
+  });
+
+  const data = (await response.json()) as ApiResult<T>;
+  if ("Ok" in data) {
+    return data.Ok;
+  }
+  if ("Err" in data) {
+    throw new Error(describeError(data.Err));
+  }
+  throw new Error(`Unexpected response (HTTP ${response.status})`);
+};
+
 export const fetchStatus = async (): Promise<StatusResponse | null> => {
   try {
-    const response = await fetch(`${BASE_URL}/api/status`, {
-      method: "GET",
-      headers: {
-        "Content-Type": "application/json"
-      }
-    });
-    
-    if (!response.ok) {
-      const errorData = await response.json();
-      throw new Error(errorData.message || "Failed to fetch status");
-    }
-    
-    const data = await response.json();
-    console.log("Status response:", data);
-    
-    if (data.Status) {
-      return data.Status;
-    } else {
-      console.error("Unexpected response format:", data);
-      throw new Error("Invalid response format");
-    }
+    const status = await callApi<StatusResponse>("/api/status", { GetStatus: {} });
+    console.log("Status response:", status);
+    return status;
   } catch (error) {
     console.error("Error fetching status:", error);
     return null;
   }
 };
 
-export const fetchHistory = async (): Promise<MessageLog[]> => {
+export const fetchHistory = async (query: HistoryQuery = {}): Promise<HistoryResponse> => {
   try {
-    const response = await fetch(`${BASE_URL}/api/history`, {
-      method: "GET",
-      headers: {
-        "Content-Type": "application/json"
-      }
-    });
-    
-    if (!response.ok) {
-      const errorData = await response.json();
-      throw new Error(errorData.message || "Failed to fetch history");
-    }
-    
-    const data = await response.json();
-    console.log("History response:", data);
-    
-    if (data.History && Array.isArray(data.History.messages)) {
-      return data.History.messages;
-    } else {
-      console.error("Unexpected response format:", data);
-      throw new Error("Invalid response format");
-    }
+    const history = await callApi<HistoryResponse>("/api/history", { GetHistory: query });
+    console.log("History response:", history);
+    return history;
   } catch (error) {
     console.error("Error fetching history:", error);
-    return [];
+    return { entries: [], next_cursor: null };
   }
 };
 
-export const clearHistory = async (): Promise<boolean> => {
+export const clearHistory = async (): Promise<ClearResponse | null> => {
   try {
-    const requestData = {
-      ClearHistory: null
-    };
-    
-    const response = await fetch(`${BASE_URL}/api`, {
-      method: "POST",
-      headers: {
-        "Content-Type": "application/json"
-      },
-      body: JSON.stringify(requestData)
-    });
-    
-    if (!response.ok) {
-      const errorData = await response.json();
-      throw new Error(errorData.message || "Failed to clear history");
-    }
-    
-    return true;
+    return await callApi<ClearResponse>("/api/clear-history", { ClearHistory: {} });
   } catch (error) {
     console.error("Error clearing history:", error);
-    return false;
+    return null;
   }
 };
 
@@ -94,36 +75,26 @@ export const sendCustomMessage = async (
 ): Promise<boolean> => {
   if (!message) return false;
 
-  const requestData = {
-    CustomMessage: {
-      message_type: messageType,
-      content: message
-    }
-  };
-
   try {
     if (messageMethod === "websocket") {
       if (!api) {
         throw new Error("WebSocket not connected");
       }
-      api.send({ data: requestData });
+      api.send({
+        data: {
+          command: "log_message",
+          message_type: messageType,
+          content: message
+        }
+      });
     } else {
-      const response = await fetch(`${BASE_URL}/api`, {
-        method: "POST",
-        headers: {
-          "Content-Type": "application/json"
-        },
-        body: JSON.stringify(requestData)
+      await callApi<SuccessResponse>("/api/log", {
+        LogCustomMessage: [messageType, message, null, []]
       });
-      
-      if (!response.ok) {
-        const errorData = await response.json();
-        throw new Error(errorData.message || "Failed to send custom message");
-      }
     }
     return true;
   } catch (error) {
     console.error("Error sending message:", error);
     return false;
   }
-}; 
\ No newline at end of file
+};